@@ -0,0 +1,133 @@
+use std::{
+    future::poll_fn,
+    io,
+    task::{Context, Poll},
+};
+
+use crate::{TcpListener, TcpStream};
+#[cfg(any(feature = "futures-io", feature = "tokio"))]
+use {
+    crate::{try_flush, try_read, try_write},
+    std::pin::Pin,
+};
+
+// Shared by the `futures-io` and `tokio` trait impls
+#[cfg(any(feature = "futures-io", feature = "tokio"))]
+impl TcpStream {
+    fn poll_read_inner(&self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let mut cm = self.h.manager.lock().unwrap();
+        let conn = cm.stream(&self.quad)?;
+        match try_read(conn, buf) {
+            Some(result) => Poll::Ready(result),
+            None => {
+                conn.read_waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    fn poll_write_inner(&self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let mut cm = self.h.manager.lock().unwrap();
+        let conn = cm.stream(&self.quad)?;
+        match try_write(conn, buf) {
+            Some(result) => Poll::Ready(result),
+            None => {
+                conn.write_waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    fn poll_flush_inner(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut cm = self.h.manager.lock().unwrap();
+        let conn = cm.stream(&self.quad)?;
+        match try_flush(conn) {
+            Some(result) => Poll::Ready(result),
+            None => {
+                conn.write_waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl TcpListener {
+    /// Poll for an incoming connection, the task is woken once one is queued.
+    pub fn poll_accept(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<TcpStream>> {
+        let mut cm = self.h.manager.lock().unwrap();
+        match self.try_accept(&mut cm)? {
+            Some(stream) => Poll::Ready(Ok(stream)),
+            None => {
+                cm.listeners.get_mut(&self.port).unwrap().waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Wait for an incoming connection without blocking the thread.
+    pub async fn accept_async(&mut self) -> io::Result<TcpStream> {
+        poll_fn(|cx| self.poll_accept(cx)).await
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl futures_io::AsyncRead for TcpStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_read_inner(cx, buf)
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl futures_io::AsyncWrite for TcpStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_write_inner(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush_inner(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(self.shutdown())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncRead for TcpStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let n = std::task::ready!(self.poll_read_inner(cx, buf.initialize_unfilled()))?;
+        buf.advance(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncWrite for TcpStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_write_inner(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush_inner(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(self.shutdown())
+    }
+}
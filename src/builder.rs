@@ -0,0 +1,226 @@
+use std::{
+    io,
+    marker::PhantomData,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use tun_rs::{DeviceBuilder, SyncDevice};
+
+use crate::{
+    Device, Interface, InterfaceHandle, RECVQUEUE_SIZE, SENDQUEUE_SIZE, SYN_BURST, SYN_RATE,
+    limiter::SourceLimiter, packet_loop, tcp,
+};
+
+/// Configuration of an [`Interface`], the TUN device below it and the stack on top.
+pub struct InterfaceBuilder {
+    name: Option<String>,
+    ipv4: Vec<(Ipv4Addr, u8)>,
+    ipv6: Vec<(Ipv6Addr, u8)>,
+    mtu: Option<u16>,
+    persist: bool,
+    owner: Option<u32>,
+    group: Option<u32>,
+    multi_queue: bool,
+    send_buffer: usize,
+    recv_buffer: usize,
+    syn_rate: (u32, u32),
+    syn_retries: u32,
+    fin_timeout: Duration,
+}
+
+impl Default for InterfaceBuilder {
+    fn default() -> Self {
+        InterfaceBuilder {
+            name: None,
+            ipv4: Vec::new(),
+            ipv6: Vec::new(),
+            mtu: None,
+            persist: false,
+            owner: None,
+            group: None,
+            multi_queue: false,
+            send_buffer: SENDQUEUE_SIZE,
+            recv_buffer: RECVQUEUE_SIZE,
+            syn_rate: (SYN_RATE, SYN_BURST),
+            syn_retries: tcp::DEFAULT_SYN_RETRIES,
+            fin_timeout: tcp::DEFAULT_FIN_TIMEOUT,
+        }
+    }
+}
+
+impl InterfaceBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Name of the TUN device, picked by the system if not set.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Add an IPv4 address, the first one is the source of outgoing connections.
+    pub fn ipv4(mut self, addr: Ipv4Addr, prefix: u8) -> Self {
+        self.ipv4.push((addr, prefix));
+        self
+    }
+
+    /// Add an IPv6 address, the first one is the source of outgoing connections.
+    pub fn ipv6(mut self, addr: Ipv6Addr, prefix: u8) -> Self {
+        self.ipv6.push((addr, prefix));
+        self
+    }
+
+    /// MTU of the TUN device, the system default if not set.
+    pub fn mtu(mut self, mtu: u16) -> Self {
+        self.mtu = Some(mtu);
+        self
+    }
+
+    /// Keep the TUN device after the interface is dropped (Linux only).
+    pub fn persist(mut self, persist: bool) -> Self {
+        self.persist = persist;
+        self
+    }
+
+    /// User allowed to open the TUN device (Linux only).
+    pub fn owner(mut self, uid: u32) -> Self {
+        self.owner = Some(uid);
+        self
+    }
+
+    /// Group allowed to open the TUN device (Linux only).
+    pub fn group(mut self, gid: u32) -> Self {
+        self.group = Some(gid);
+        self
+    }
+
+    /// Create the TUN device with multiple queues (Linux only).
+    pub fn multi_queue(mut self, multi_queue: bool) -> Self {
+        self.multi_queue = multi_queue;
+        self
+    }
+
+    /// Bytes a stream may queue for sending before writes block.
+    pub fn send_buffer_size(mut self, size: usize) -> Self {
+        self.send_buffer = size;
+        self
+    }
+
+    /// Bytes a stream buffers for reading, this bounds the advertised window.
+    pub fn recv_buffer_size(mut self, size: usize) -> Self {
+        self.recv_buffer = size;
+        self
+    }
+
+    /// SYNs handled per second from a single source address, and back to back.
+    pub fn syn_rate_limit(mut self, per_second: u32, burst: u32) -> Self {
+        self.syn_rate = (per_second, burst);
+        self
+    }
+
+    /// Retransmissions of a SYN or SYN-ACK before the handshake is abandoned.
+    pub fn syn_retries(mut self, retries: u32) -> Self {
+        self.syn_retries = retries;
+        self
+    }
+
+    /// Time a dropped stream waits in FIN-WAIT-2 for the peer's FIN before it is reset,
+    /// 60 seconds by default.
+    pub fn fin_timeout(mut self, timeout: Duration) -> Self {
+        self.fin_timeout = timeout;
+        self
+    }
+
+    /// Create the TUN device and start the stack on it.
+    pub fn build(self) -> io::Result<Interface> {
+        let mut builder = DeviceBuilder::new();
+        if let Some(name) = &self.name {
+            builder = builder.name(name);
+        }
+        if let Some(mtu) = self.mtu {
+            builder = builder.mtu(mtu);
+        }
+        // Only a single IPv4 address can be configured up front
+        if let Some(&(addr, prefix)) = self.ipv4.first() {
+            builder = builder.ipv4(addr, prefix, None);
+        }
+        for &(addr, prefix) in &self.ipv6 {
+            builder = builder.ipv6(addr, prefix);
+        }
+        #[cfg(target_os = "linux")]
+        if self.multi_queue {
+            builder = builder.multi_queue(true);
+        }
+
+        let nic = builder.build_sync()?;
+        for &(addr, prefix) in self.ipv4.iter().skip(1) {
+            nic.add_address_v4(addr, prefix)?;
+        }
+        self.configure(&nic)?;
+        #[cfg(unix)]
+        nic.set_nonblocking(true)?;
+
+        self.build_with_device(nic)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn configure(&self, nic: &SyncDevice) -> io::Result<()> {
+        if self.persist {
+            nic.persist()?;
+        }
+        if let Some(uid) = self.owner {
+            nic.user(uid as i32)?;
+        }
+        if let Some(gid) = self.group {
+            nic.group(gid as i32)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn configure(&self, _nic: &SyncDevice) -> io::Result<()> {
+        if self.persist || self.owner.is_some() || self.group.is_some() || self.multi_queue {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "persistence, ownership and multi-queue are only supported on Linux",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Start the stack on `nic`, the TUN device options are ignored.
+    pub fn build_with_device<D: Device>(self, nic: D) -> io::Result<Interface<D>> {
+        let ih: InterfaceHandle = Arc::default();
+        {
+            let mut cm = ih.manager.lock().unwrap();
+            cm.config = tcp::Config {
+                mtu: nic.mtu()? as usize,
+                send_buffer: self.send_buffer,
+                recv_buffer: self.recv_buffer,
+                syn_retries: self.syn_retries,
+                fin_timeout: self.fin_timeout,
+            };
+            cm.addrs = self
+                .ipv4
+                .iter()
+                .map(|&(addr, _)| IpAddr::from(addr))
+                .chain(self.ipv6.iter().map(|&(addr, _)| IpAddr::from(addr)))
+                .collect();
+            cm.syn_limiter = SourceLimiter::new(self.syn_rate.0, self.syn_rate.1);
+        }
+        let jh = {
+            let handle = ih.clone();
+            thread::spawn(move || packet_loop(handle, nic))
+        };
+
+        Ok(Interface {
+            ih: Some(ih),
+            jh: Some(jh),
+            device: PhantomData,
+        })
+    }
+}
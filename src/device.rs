@@ -0,0 +1,109 @@
+use std::{
+    io,
+    sync::{
+        OnceLock,
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    },
+    time::Duration,
+};
+
+use tun_rs::{InterruptEvent, SyncDevice};
+
+/// A network device exchanging raw IP packets with the stack.
+pub trait Device: Send + 'static {
+    /// Send a single IP packet.
+    fn send(&self, packet: &[u8]) -> io::Result<usize>;
+
+    /// Receive a single IP packet, waiting at most `timeout` for it.
+    ///
+    /// Fails with `TimedOut` or `WouldBlock` when no packet arrived in time, any other
+    /// error stops the stack.
+    fn recv_timeout(&self, buf: &mut [u8], timeout: Duration) -> io::Result<usize>;
+
+    /// Maximum size of a packet, including the IP header.
+    fn mtu(&self) -> io::Result<u16>;
+}
+
+/// TUN device, on Unix it has to be in nonblocking mode.
+impl Device for SyncDevice {
+    fn send(&self, packet: &[u8]) -> io::Result<usize> {
+        SyncDevice::send(self, packet)
+    }
+
+    fn recv_timeout(&self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        // Never triggered, it only lets the read time out
+        static NEVER: OnceLock<InterruptEvent> = OnceLock::new();
+        let event = NEVER.get_or_init(|| InterruptEvent::new().expect("failed to create event"));
+        self.recv_intr_timeout(buf, event, Some(timeout))
+    }
+
+    fn mtu(&self) -> io::Result<u16> {
+        (**self).mtu()
+    }
+}
+
+/// One end of an in-memory point-to-point link.
+///
+/// Packets sent on one end are received on the other, so two interfaces can talk to
+/// each other, or a test can play the peer of an interface by hand.
+pub struct PipeDevice {
+    tx: Sender<Vec<u8>>,
+    rx: Receiver<Vec<u8>>,
+    mtu: u16,
+}
+
+impl PipeDevice {
+    /// Both ends of a new link.
+    pub fn pair(mtu: u16) -> (PipeDevice, PipeDevice) {
+        let (a_tx, b_rx) = mpsc::channel();
+        let (b_tx, a_rx) = mpsc::channel();
+        (
+            PipeDevice {
+                tx: a_tx,
+                rx: a_rx,
+                mtu,
+            },
+            PipeDevice {
+                tx: b_tx,
+                rx: b_rx,
+                mtu,
+            },
+        )
+    }
+}
+
+impl Device for PipeDevice {
+    fn send(&self, packet: &[u8]) -> io::Result<usize> {
+        if packet.len() > self.mtu as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "packet exceeds the MTU",
+            ));
+        }
+        // Like on a real link, packets to a vanished peer are lost
+        let _ = self.tx.send(packet.to_vec());
+        Ok(packet.len())
+    }
+
+    fn recv_timeout(&self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        match self.rx.recv_timeout(timeout) {
+            Ok(packet) => Ok(copy_packet(&packet, buf)),
+            Err(RecvTimeoutError::Timeout) => Err(io::ErrorKind::TimedOut.into()),
+            Err(RecvTimeoutError::Disconnected) => Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "other end of the pipe was dropped",
+            )),
+        }
+    }
+
+    fn mtu(&self) -> io::Result<u16> {
+        Ok(self.mtu)
+    }
+}
+
+/// Packets that do not fit into `buf` are truncated
+fn copy_packet(packet: &[u8], buf: &mut [u8]) -> usize {
+    let n = std::cmp::min(packet.len(), buf.len());
+    buf[..n].copy_from_slice(&packet[..n]);
+    n
+}
@@ -0,0 +1,162 @@
+use std::{
+    io::{self, Read, Write},
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd, RawFd},
+        unix::net::UnixStream,
+    },
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
+};
+
+use crate::{TcpListener, TcpStream, tcp::Available};
+
+/// Interest of a new socket, `WRITE` is left out as it is set nearly all the time and
+/// would keep a level-triggered event loop spinning.
+const DEFAULT_INTEREST: Available = Available::READ
+    .union(Available::HANGUP)
+    .union(Available::ERROR);
+
+/// File descriptor that is readable while a socket is ready for its interest.
+///
+/// The packet loop signals it by writing a byte into a socket pair, so it can be
+/// registered with epoll, mio or any other readiness based event loop.
+pub(crate) struct Event {
+    reader: UnixStream,
+    writer: UnixStream,
+    interest: AtomicU8,
+    signaled: AtomicBool,
+}
+
+impl Event {
+    pub(crate) fn new() -> io::Result<Self> {
+        let (reader, writer) = UnixStream::pair()?;
+        reader.set_nonblocking(true)?;
+        writer.set_nonblocking(true)?;
+        Ok(Event {
+            reader,
+            writer,
+            interest: AtomicU8::new(DEFAULT_INTEREST.bits()),
+            signaled: AtomicBool::new(false),
+        })
+    }
+
+    pub(crate) fn set_interest(&self, interest: Available) {
+        self.interest.store(interest.bits(), Ordering::Relaxed);
+    }
+
+    /// Signal or clear the descriptor for the current readiness of the socket.
+    pub(crate) fn update(&self, available: &Available) {
+        let interest = Available::from_bits_truncate(self.interest.load(Ordering::Relaxed));
+        let ready = available.intersects(interest);
+
+        if ready && !self.signaled.swap(true, Ordering::AcqRel) {
+            // A full buffer still leaves the descriptor readable
+            let _ = (&self.writer).write(&[1]);
+        } else if !ready && self.signaled.swap(false, Ordering::AcqRel) {
+            let mut buf = [0; 16];
+            while matches!((&self.reader).read(&mut buf), Ok(n) if n > 0) {}
+        }
+    }
+}
+
+impl AsFd for Event {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.reader.as_fd()
+    }
+}
+
+impl AsFd for TcpStream {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.event.as_fd()
+    }
+}
+
+impl AsRawFd for TcpStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.event.as_fd().as_raw_fd()
+    }
+}
+
+impl AsFd for TcpListener {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.event.as_fd()
+    }
+}
+
+impl AsRawFd for TcpListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.event.as_fd().as_raw_fd()
+    }
+}
+
+#[cfg(feature = "mio")]
+mod source {
+    use std::{io, os::fd::AsRawFd};
+
+    use mio::{Interest, Registry, Token, event::Source, unix::SourceFd};
+
+    use crate::{TcpListener, TcpStream, tcp::Available};
+
+    /// Readiness the descriptor is signaled for, given the interest of the registration
+    fn interest(interest: Interest) -> Available {
+        let mut available = Available::empty();
+        if interest.is_readable() {
+            available |= Available::READ | Available::HANGUP | Available::ERROR;
+        }
+        if interest.is_writable() {
+            available |= Available::WRITE | Available::ERROR;
+        }
+        available
+    }
+
+    /// The descriptor is always registered readable, whatever the interest is.
+    impl Source for TcpStream {
+        fn register(
+            &mut self,
+            registry: &Registry,
+            token: Token,
+            interests: Interest,
+        ) -> io::Result<()> {
+            self.set_interest(interest(interests))?;
+            SourceFd(&self.as_raw_fd()).register(registry, token, Interest::READABLE)
+        }
+
+        fn reregister(
+            &mut self,
+            registry: &Registry,
+            token: Token,
+            interests: Interest,
+        ) -> io::Result<()> {
+            self.set_interest(interest(interests))?;
+            SourceFd(&self.as_raw_fd()).reregister(registry, token, Interest::READABLE)
+        }
+
+        fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+            SourceFd(&self.as_raw_fd()).deregister(registry)
+        }
+    }
+
+    /// The descriptor is readable while connections wait to be accepted.
+    impl Source for TcpListener {
+        fn register(
+            &mut self,
+            registry: &Registry,
+            token: Token,
+            _interests: Interest,
+        ) -> io::Result<()> {
+            SourceFd(&self.as_raw_fd()).register(registry, token, Interest::READABLE)
+        }
+
+        fn reregister(
+            &mut self,
+            registry: &Registry,
+            token: Token,
+            _interests: Interest,
+        ) -> io::Result<()> {
+            SourceFd(&self.as_raw_fd()).reregister(registry, token, Interest::READABLE)
+        }
+
+        fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+            SourceFd(&self.as_raw_fd()).deregister(registry)
+        }
+    }
+}
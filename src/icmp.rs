@@ -0,0 +1,208 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use etherparse::{
+    IcmpEchoHeader, Icmpv4Slice, Icmpv4Type, Icmpv6Slice, Icmpv6Type, IpNumber, IpSlice,
+    PacketBuilder,
+};
+
+use crate::{TTL, device::Device, limiter::TokenBucket, tcp::to_ipv6};
+
+/// Echo requests answered per second
+const ECHO_REPLY_RATE: u32 = 100;
+/// Echo requests answered back to back
+const ECHO_REPLY_BURST: u32 = 50;
+
+/// Echo requests and replies, for both ICMP and ICMPv6
+pub(crate) struct Icmp {
+    /// limits the echo replies we send
+    replies: TokenBucket,
+    /// identifier of our echo requests
+    id: u16,
+    next_seq: u16,
+    /// outstanding echo requests by sequence number
+    echoes: HashMap<u16, Echo>,
+    /// echo requests waiting for the packet loop to send them
+    outgoing: VecDeque<(u16, Vec<u8>)>,
+}
+
+struct Echo {
+    remote: IpAddr,
+    sent_at: Option<Instant>,
+    rtt: Option<Duration>,
+}
+
+impl Default for Icmp {
+    fn default() -> Self {
+        Icmp {
+            replies: TokenBucket::new(ECHO_REPLY_RATE, ECHO_REPLY_BURST),
+            id: rand::random(),
+            next_seq: 0,
+            echoes: HashMap::new(),
+            outgoing: VecDeque::new(),
+        }
+    }
+}
+
+impl Icmp {
+    /// Queue an echo request, returns its sequence number.
+    pub(crate) fn request(
+        &mut self,
+        local: IpAddr,
+        remote: IpAddr,
+        payload: &[u8],
+        mtu: usize,
+    ) -> io::Result<u16> {
+        let seq = self.next_seq;
+        let echo = IcmpEchoHeader { id: self.id, seq };
+        let packet = echo_packet(local, remote, true, echo, payload);
+        // Fragmentation is not supported
+        if packet.len() > mtu {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "echo request does not fit into a packet",
+            ));
+        }
+
+        self.next_seq = seq.wrapping_add(1);
+        self.echoes.insert(
+            seq,
+            Echo {
+                remote,
+                sent_at: None,
+                rtt: None,
+            },
+        );
+        self.outgoing.push_back((seq, packet));
+        Ok(seq)
+    }
+
+    /// Round-trip time of an echo request, once it was answered.
+    pub(crate) fn rtt(&self, seq: u16) -> Option<Duration> {
+        self.echoes.get(&seq)?.rtt
+    }
+
+    /// Forget an echo request, answered or not.
+    pub(crate) fn remove(&mut self, seq: u16) {
+        self.echoes.remove(&seq);
+        self.outgoing.retain(|&(s, _)| s != seq);
+    }
+
+    /// Send the queued echo requests.
+    pub(crate) fn on_tick(&mut self, nic: &dyn Device) -> io::Result<()> {
+        while let Some((seq, packet)) = self.outgoing.pop_front() {
+            nic.send(&packet)?;
+            if let Some(echo) = self.echoes.get_mut(&seq) {
+                echo.sent_at = Some(Instant::now());
+            }
+        }
+        Ok(())
+    }
+
+    /// Answer echo requests to `addrs` and match echo replies to our requests.
+    ///
+    /// Returns `true` if one of our requests was answered.
+    pub(crate) fn on_packet(
+        &mut self,
+        nic: &dyn Device,
+        iph: &IpSlice,
+        addrs: &[IpAddr],
+    ) -> io::Result<bool> {
+        let (src, dst) = (iph.source_addr(), iph.destination_addr());
+        let payload = iph.payload().payload;
+        let echo = match iph.payload_ip_number() {
+            IpNumber::ICMP => match Icmpv4Slice::from_slice(payload) {
+                Ok(icmp) if icmp.icmp_type().calc_checksum(icmp.payload()) == icmp.checksum() => {
+                    match icmp.icmp_type() {
+                        Icmpv4Type::EchoRequest(echo) => Some((true, echo, icmp.payload())),
+                        Icmpv4Type::EchoReply(echo) => Some((false, echo, icmp.payload())),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            },
+            _ => match Icmpv6Slice::from_slice(payload) {
+                Ok(icmp)
+                    if icmp.is_checksum_valid(to_ipv6(src).octets(), to_ipv6(dst).octets()) =>
+                {
+                    match icmp.icmp_type() {
+                        Icmpv6Type::EchoRequest(echo) => Some((true, echo, icmp.payload())),
+                        Icmpv6Type::EchoReply(echo) => Some((false, echo, icmp.payload())),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            },
+        };
+
+        match echo {
+            Some((true, echo, data)) if addrs.contains(&dst) => {
+                self.reply(nic, dst, src, echo, data)?;
+                Ok(false)
+            }
+            Some((false, echo, _)) if echo.id == self.id => {
+                let now = Instant::now();
+                match self.echoes.get_mut(&echo.seq) {
+                    Some(pending) if pending.remote == src && pending.rtt.is_none() => {
+                        pending.rtt = pending.sent_at.map(|sent_at| now - sent_at);
+                        Ok(pending.rtt.is_some())
+                    }
+                    _ => Ok(false),
+                }
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn reply(
+        &mut self,
+        nic: &dyn Device,
+        local: IpAddr,
+        remote: IpAddr,
+        echo: IcmpEchoHeader,
+        payload: &[u8],
+    ) -> io::Result<()> {
+        if !self.replies.try_take(Instant::now()) {
+            return Ok(());
+        }
+
+        nic.send(&echo_packet(local, remote, false, echo, payload))?;
+        Ok(())
+    }
+}
+
+/// An echo request or reply from `local` to `remote`
+fn echo_packet(
+    local: IpAddr,
+    remote: IpAddr,
+    request: bool,
+    echo: IcmpEchoHeader,
+    payload: &[u8],
+) -> Vec<u8> {
+    let mut packet = Vec::new();
+    match (local, remote) {
+        (IpAddr::V4(local), IpAddr::V4(remote)) => {
+            let builder = PacketBuilder::ipv4(local.octets(), remote.octets(), TTL);
+            match request {
+                true => builder.icmpv4_echo_request(echo.id, echo.seq),
+                false => builder.icmpv4_echo_reply(echo.id, echo.seq),
+            }
+            .write(&mut packet, payload)
+        }
+        (local, remote) => {
+            let builder =
+                PacketBuilder::ipv6(to_ipv6(local).octets(), to_ipv6(remote).octets(), TTL);
+            match request {
+                true => builder.icmpv6_echo_request(echo.id, echo.seq),
+                false => builder.icmpv6_echo_reply(echo.id, echo.seq),
+            }
+            .write(&mut packet, payload)
+        }
+    }
+    .expect("failed to build echo packet");
+    packet
+}
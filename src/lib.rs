@@ -1,20 +1,58 @@
 use std::{
-    collections::{HashMap, VecDeque, hash_map::Entry},
+    collections::{HashMap, HashSet, VecDeque, hash_map::Entry},
     io::{self, Read, Write},
-    net::Ipv4Addr,
-    sync::{Arc, Condvar, Mutex},
+    marker::PhantomData,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    ops::RangeInclusive,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    task::Waker,
     thread,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
-use etherparse::IpNumber;
-use tun_rs::{DeviceBuilder, SyncDevice};
+use etherparse::{IpNumber, IpSlice, TcpHeaderSlice};
+use tun_rs::SyncDevice;
 
-use crate::tcp::{Available, Connection};
+#[cfg(unix)]
+use crate::event::Event;
+use crate::{
+    limiter::{SourceLimiter, TokenBucket},
+    tcp::{Connection, congestion, cookie::SynCookies},
+};
+
+pub use crate::{
+    builder::InterfaceBuilder,
+    device::{Device, PipeDevice},
+    tcp::congestion::{Bbr, CongestionControl, Cubic, RateSample, Reno},
+    tcp::{Available, Keepalive},
+    udp::UdpSocket,
+};
 
+#[cfg(feature = "async")]
+mod async_io;
+mod builder;
+mod device;
+#[cfg(unix)]
+mod event;
+mod icmp;
+mod limiter;
 mod tcp;
+mod udp;
 
-const SENDQUEUE_SIZE: usize = 1024;
+const SENDQUEUE_SIZE: usize = 256 * 1024;
+const RECVQUEUE_SIZE: usize = 256 * 1024;
+const EPHEMERAL_PORTS: RangeInclusive<u16> = 49152..=65535;
+const TICK_INTERVAL: Duration = Duration::from_millis(10);
+/// Hop limit of the packets we send
+const TTL: u8 = 64;
+/// RSTs sent per second, and back to back
+const RESET_RATE: u32 = 100;
+const RESET_BURST: u32 = 50;
+/// SYNs handled per second from a single source, and back to back
+const SYN_RATE: u32 = 100;
+const SYN_BURST: u32 = 200;
+/// Backlog of listeners bound without one
+const DEFAULT_BACKLOG: usize = 128;
 
 type InterfaceHandle = Arc<Handler>;
 
@@ -23,102 +61,478 @@ struct Handler {
     manager: Mutex<ConnectionManager>,
     pending_var: Condvar,
     rcv_var: Condvar,
+    snd_var: Condvar,
+    est_var: Condvar,
+    ping_var: Condvar,
 }
 
 #[derive(Hash, Eq, PartialEq, Debug, Clone, Copy)]
 struct Quad {
-    src: (Ipv4Addr, u16),
-    dst: (Ipv4Addr, u16),
+    src: SocketAddr,
+    dst: SocketAddr,
 }
 
-pub struct Interface {
+pub struct Interface<D: Device = SyncDevice> {
     ih: Option<InterfaceHandle>,
     jh: Option<thread::JoinHandle<std::io::Result<()>>>,
+    /// the device itself is owned by the packet loop
+    device: PhantomData<fn() -> D>,
 }
 
-impl Drop for Interface {
+impl<D: Device> Drop for Interface<D> {
     fn drop(&mut self) {
         self.ih.as_mut().unwrap().manager.lock().unwrap().terminate = true;
 
         drop(self.ih.take());
-        self.jh.take().unwrap().join().unwrap().unwrap();
+        // A device error already failed every socket, only a panic is passed on
+        let _ = self.jh.take().unwrap().join().unwrap();
     }
 }
 
-#[derive(Default)]
 pub struct ConnectionManager {
     terminate: bool,
+    /// local addresses, the first of each family is used for outgoing connections
+    addrs: Vec<IpAddr>,
     connection: HashMap<Quad, tcp::Connection>,
-    pending: HashMap<u16, VecDeque<Quad>>,
+    listeners: HashMap<u16, Listener>,
+    udp: HashMap<u16, udp::Socket>,
+    icmp: icmp::Icmp,
+    /// limits the RSTs we send
+    resets: TokenBucket,
+    /// limits the SYNs we handle per source
+    syn_limiter: SourceLimiter,
+    cookies: SynCookies,
+    syn_stats: SynStats,
+    congestion: congestion::Factory,
+    config: tcp::Config,
 }
 
-fn packet_loop(ih: InterfaceHandle, nic: SyncDevice) -> std::io::Result<()> {
-    let mut buf = [0u8; 1500];
+/// What a listener does with a SYN once its backlog is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ListenOverflow {
+    /// Ignore the SYN, the peer retries it later
+    #[default]
+    Drop,
+    /// Refuse the connection with a RST
+    Reset,
+}
 
-    loop {
-        if ih.manager.lock().unwrap().terminate {
-            break Ok(());
+/// SYNs received by the listeners of an interface, and what became of them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SynStats {
+    /// SYNs for a listening port
+    pub received: u64,
+    /// SYNs dropped by the per-source rate limit
+    pub rate_limited: u64,
+    /// SYNs and cookie ACKs dropped or refused because the backlog was full
+    pub overflowed: u64,
+    /// SYNs answered with a SYN cookie
+    pub cookies_sent: u64,
+    /// handshakes completed with a valid SYN cookie
+    pub cookies_accepted: u64,
+}
+
+/// A bound port and the connections waiting to be accepted on it
+struct Listener {
+    /// connections still in the handshake
+    syn_queue: HashSet<Quad>,
+    /// established connections waiting for accept
+    pending: VecDeque<Quad>,
+    /// connections in both queues together
+    backlog: usize,
+    overflow: ListenOverflow,
+    /// whether SYNs are answered with cookies while the SYN queue is full
+    syn_cookies: bool,
+    /// task waiting in an async accept
+    waker: Option<Waker>,
+    /// whether accept fails with `WouldBlock` instead of waiting
+    nonblocking: bool,
+    #[cfg(unix)]
+    event: Arc<Event>,
+}
+
+impl Listener {
+    /// Whether another connection may start its handshake
+    fn has_room(&self) -> bool {
+        self.syn_queue.len() + self.pending.len() < self.backlog
+    }
+
+    fn availability(&self) -> Available {
+        if self.pending.is_empty() {
+            Available::empty()
+        } else {
+            Available::READ
+        }
+    }
+
+    /// Queue a new connection and wake whoever waits in accept.
+    fn push(&mut self, quad: Quad) {
+        self.pending.push_back(quad);
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+        #[cfg(unix)]
+        self.event.update(&self.availability());
+    }
+}
+
+impl Default for ConnectionManager {
+    fn default() -> Self {
+        ConnectionManager {
+            terminate: false,
+            addrs: Vec::new(),
+            connection: HashMap::new(),
+            listeners: HashMap::new(),
+            udp: HashMap::new(),
+            icmp: icmp::Icmp::default(),
+            resets: TokenBucket::new(RESET_RATE, RESET_BURST),
+            syn_limiter: SourceLimiter::new(SYN_RATE, SYN_BURST),
+            cookies: SynCookies::new(),
+            syn_stats: SynStats::default(),
+            congestion: congestion::default_factory(),
+            config: tcp::Config {
+                mtu: 1500,
+                send_buffer: SENDQUEUE_SIZE,
+                recv_buffer: RECVQUEUE_SIZE,
+                syn_retries: tcp::DEFAULT_SYN_RETRIES,
+                fin_timeout: tcp::DEFAULT_FIN_TIMEOUT,
+            },
+        }
+    }
+}
+
+impl ConnectionManager {
+    /// The connection behind a stream
+    fn stream(&mut self, quad: &Quad) -> io::Result<&mut Connection> {
+        if self.terminate {
+            return Err(shut_down_error());
+        }
+        self.connection.get_mut(quad).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "stream was terminated unexpectedly",
+            )
+        })
+    }
+
+    /// Hand a connection that left the handshake to its listener.
+    ///
+    /// Returns `true` if it was queued for accept.
+    fn on_handshake(&mut self, quad: Quad) -> bool {
+        let Some(listener) = self.listeners.get_mut(&quad.dst.port()) else {
+            return false;
+        };
+        if !listener.syn_queue.remove(&quad) {
+            return false;
+        }
+        // Reset during the handshake, the connection is reaped once unowned
+        if self.connection.get(&quad).is_none_or(|con| con.is_closed()) {
+            return false;
+        }
+        listener.push(quad);
+        true
+    }
+
+    fn ephemeral_port(&self, local: IpAddr, remote: SocketAddr) -> io::Result<u16> {
+        find_ephemeral_port(|port| {
+            let quad = Quad {
+                src: remote,
+                dst: SocketAddr::new(local, port),
+            };
+            !self.listeners.contains_key(&port) && !self.connection.contains_key(&quad)
+        })
+    }
+}
+
+/// The first port of `EPHEMERAL_PORTS` that is `free`, the scan starts at a random port.
+fn find_ephemeral_port(free: impl Fn(u16) -> bool) -> io::Result<u16> {
+    let (start, end) = (*EPHEMERAL_PORTS.start(), *EPHEMERAL_PORTS.end());
+    let span = (end - start) as u32 + 1;
+    let offset = rand::random_range(0..span);
+
+    (0..span)
+        .map(|i| start + ((offset + i) % span) as u16)
+        .find(|&port| free(port))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                "no ephemeral port available",
+            )
+        })
+}
+
+fn on_tick(ih: &InterfaceHandle, nic: &dyn Device) -> std::io::Result<()> {
+    let mut readable = false;
+    let mut writable = false;
+    let mut connected = false;
+
+    let mut lock = ih.manager.lock().unwrap();
+    let cm = &mut *lock;
+    for con in cm.connection.values_mut() {
+        let connecting = con.is_connecting();
+        let available = con.on_tick(nic)?;
+        con.wake(&available);
+        readable |= available.contains(Available::READ);
+        writable |= available.contains(Available::WRITE);
+        connected |= connecting && !con.is_connecting();
+    }
+    for socket in cm.udp.values_mut() {
+        writable |= socket.on_tick(nic)?;
+    }
+    cm.icmp.on_tick(nic)?;
+    // Reap closed connections that no stream refers to anymore
+    cm.connection.retain(|_, con| con.owned || !con.is_closed());
+    for listener in cm.listeners.values_mut() {
+        listener
+            .syn_queue
+            .retain(|quad| cm.connection.contains_key(quad));
+    }
+    drop(lock);
+
+    if readable {
+        ih.rcv_var.notify_all();
+    }
+    if writable {
+        ih.snd_var.notify_all();
+    }
+    if connected {
+        ih.est_var.notify_all();
+    }
+
+    Ok(())
+}
+
+/// The address of `addrs` in the family of `remote`
+fn local_ip(addrs: &[IpAddr], remote: IpAddr) -> io::Result<IpAddr> {
+    addrs
+        .iter()
+        .find(|addr| addr.is_ipv4() == remote.is_ipv4())
+        .copied()
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                "no local address of the same family",
+            )
+        })
+}
+
+/// The error of every socket once the interface is gone
+fn shut_down_error() -> io::Error {
+    io::Error::new(io::ErrorKind::NotConnected, "interface was shut down")
+}
+
+/// Deadline `timeout` from now, `None` (no deadline) if that is beyond what `Instant` holds.
+fn deadline(timeout: Duration) -> Option<Instant> {
+    Instant::now().checked_add(timeout)
+}
+
+/// Wait on `var` until notified, failing with `TimedOut` once `deadline` has passed.
+fn wait_until<'a>(
+    var: &Condvar,
+    cm: MutexGuard<'a, ConnectionManager>,
+    deadline: Option<Instant>,
+) -> io::Result<MutexGuard<'a, ConnectionManager>> {
+    let Some(deadline) = deadline else {
+        return Ok(var.wait(cm).unwrap());
+    };
+
+    let now = Instant::now();
+    if now >= deadline {
+        return Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "operation timed out",
+        ));
+    }
+    Ok(var.wait_timeout(cm, deadline - now).unwrap().0)
+}
+
+fn packet_loop<D: Device>(ih: InterfaceHandle, nic: D) -> std::io::Result<()> {
+    let result = run(&ih, &nic);
+    shut_down(&ih);
+    result
+}
+
+/// Fail every socket and wake whoever waits on one, the stack is not running anymore.
+fn shut_down(ih: &InterfaceHandle) {
+    let mut lock = ih.manager.lock().unwrap();
+    let cm = &mut *lock;
+    cm.terminate = true;
+    for con in cm.connection.values_mut() {
+        con.error.get_or_insert(io::ErrorKind::NotConnected);
+        con.wake(&Available::all());
+    }
+    for listener in cm.listeners.values_mut() {
+        if let Some(waker) = listener.waker.take() {
+            waker.wake();
         }
+        #[cfg(unix)]
+        listener.event.update(&Available::ERROR);
+    }
+    drop(lock);
+
+    ih.pending_var.notify_all();
+    ih.rcv_var.notify_all();
+    ih.snd_var.notify_all();
+    ih.est_var.notify_all();
+    ih.ping_var.notify_all();
+}
 
-        let now = Instant::now();
+/// Handle a segment that matches no connection, which may be for a listener.
+///
+/// Returns `true` if a connection was queued for accept.
+fn on_listen(
+    cm: &mut ConnectionManager,
+    nic: &dyn Device,
+    q: Quad,
+    iph: &IpSlice,
+    tcp_h: TcpHeaderSlice,
+    data: &[u8],
+) -> io::Result<bool> {
+    let Some(listener) = cm.listeners.get_mut(&q.dst.port()) else {
+        tcp::reset(nic, &mut cm.resets, iph, &tcp_h, data)?;
+        return Ok(false);
+    };
+    if tcp_h.rst() {
+        return Ok(false);
+    }
 
+    if tcp_h.syn() && !tcp_h.ack() {
+        cm.syn_stats.received += 1;
+        if !cm.syn_limiter.try_take(q.src.ip(), Instant::now()) {
+            cm.syn_stats.rate_limited += 1;
+        } else if listener.has_room() {
+            if let Some(connection) =
+                Connection::accept(nic, iph, tcp_h, data, cm.config, &cm.congestion)?
+            {
+                cm.connection.insert(q, connection);
+                listener.syn_queue.insert(q);
+            }
+        } else if listener.syn_cookies && listener.pending.len() < listener.backlog {
+            // The SYN queue is full, answer without keeping any state
+            tcp::send_cookie(nic, iph, &tcp_h, cm.config, &mut cm.cookies)?;
+            cm.syn_stats.cookies_sent += 1;
+        } else {
+            cm.syn_stats.overflowed += 1;
+            if listener.overflow == ListenOverflow::Reset {
+                tcp::reset(nic, &mut cm.resets, iph, &tcp_h, data)?;
+            }
+        }
+        return Ok(false);
+    }
+
+    if tcp_h.ack() {
+        if listener.syn_cookies
+            && cm.cookies.recently_sent()
+            && !tcp_h.syn()
+            && let Some(mut connection) =
+                Connection::from_cookie(iph, &tcp_h, cm.config, &cm.congestion, &cm.cookies)
+        {
+            if listener.pending.len() >= listener.backlog {
+                cm.syn_stats.overflowed += 1;
+                if listener.overflow == ListenOverflow::Reset {
+                    tcp::reset(nic, &mut cm.resets, iph, &tcp_h, data)?;
+                }
+                return Ok(false);
+            }
+
+            connection.on_packet(nic, &mut cm.resets, iph, tcp_h, data)?;
+            if connection.is_connecting() || connection.is_closed() {
+                return Ok(false);
+            }
+            cm.syn_stats.cookies_accepted += 1;
+            cm.connection.insert(q, connection);
+            listener.push(q);
+            return Ok(true);
+        }
+
+        // Any acknowledgment is bad in LISTEN
+        tcp::reset(nic, &mut cm.resets, iph, &tcp_h, data)?;
+    }
+    Ok(false)
+}
+
+fn run<D: Device>(ih: &InterfaceHandle, nic: &D) -> std::io::Result<()> {
+    let mut buf = vec![0u8; ih.manager.lock().unwrap().config.mtu];
+    let mut last_tick = Instant::now();
+
+    loop {
         let n = loop {
-            match nic.try_recv(&mut buf) {
-                Ok(n) => break n,
-                Err(_) => {
-                    if now.elapsed().as_millis() > 100 {
-                        for con in ih.manager.lock().unwrap().connection.values_mut() {
-                            con.on_tick(&nic)?;
-                        }
-                        continue;
-                    }
+            if last_tick.elapsed() >= TICK_INTERVAL {
+                // Checked on every tick, the device may stay silent for a long time
+                if ih.manager.lock().unwrap().terminate {
+                    return Ok(());
                 }
-            };
+                on_tick(ih, nic)?;
+                last_tick = Instant::now();
+            }
+
+            // Wait for a packet until the next tick is due
+            let timeout = TICK_INTERVAL.saturating_sub(last_tick.elapsed());
+            match nic.recv_timeout(&mut buf, timeout) {
+                Ok(n) => break n,
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::TimedOut
+                            | io::ErrorKind::WouldBlock
+                            | io::ErrorKind::Interrupted
+                    ) => {}
+                Err(e) => return Err(e),
+            }
         };
 
-        match etherparse::Ipv4HeaderSlice::from_slice(&buf[..n]) {
+        match IpSlice::from_slice(&buf[..n]) {
             Ok(iph) => {
                 let src = iph.source_addr();
                 let dst = iph.destination_addr();
                 // println!(
-                //     "Received packet: {} -> {}, protocol: {:?}, length: {}",
+                //     "Received packet: {} -> {}, protocol: {:?}",
                 //     src,
                 //     dst,
-                //     iph.protocol(),
-                //     iph.total_len()
+                //     iph.payload_ip_number(),
                 // );
 
-                if iph.protocol() == IpNumber::TCP {
-                    match etherparse::TcpHeaderSlice::from_slice(&buf[iph.slice().len()..n]) {
+                // Fragments are not reassembled
+                if iph.payload_ip_number() == IpNumber::TCP && !iph.is_fragmenting_payload() {
+                    let segment = iph.payload().payload;
+                    match TcpHeaderSlice::from_slice(segment) {
                         Ok(tcp_h) => {
-                            let data = &buf[iph.slice().len() + tcp_h.slice().len()..n];
+                            let data = &segment[tcp_h.slice().len()..];
                             let mut lock = ih.manager.lock().unwrap();
                             let cm = &mut *lock;
                             let q = Quad {
-                                src: (src, tcp_h.source_port()),
-                                dst: (dst, tcp_h.destination_port()),
+                                src: SocketAddr::new(src, tcp_h.source_port()),
+                                dst: SocketAddr::new(dst, tcp_h.destination_port()),
                             };
                             match cm.connection.entry(q) {
                                 Entry::Occupied(mut occupied_entry) => {
-                                    let available = occupied_entry
-                                        .get_mut()
-                                        .on_packet(&nic, iph, tcp_h, data)?;
+                                    let con = occupied_entry.get_mut();
+                                    let connecting = con.is_connecting();
+                                    let available =
+                                        con.on_packet(nic, &mut cm.resets, &iph, tcp_h, data)?;
+                                    let connected = connecting && !con.is_connecting();
+                                    con.wake(&available);
+                                    let accepted = connected && cm.on_handshake(q);
 
                                     drop(lock);
                                     if available.contains(Available::READ) {
                                         ih.rcv_var.notify_all();
                                     }
+                                    if available.contains(Available::WRITE) {
+                                        ih.snd_var.notify_all();
+                                    }
+                                    if connected {
+                                        ih.est_var.notify_all();
+                                    }
+                                    if accepted {
+                                        ih.pending_var.notify_all();
+                                    }
                                 }
-                                Entry::Vacant(vacant_entry) => {
-                                    if let Some(pending) =
-                                        cm.pending.get_mut(&tcp_h.destination_port())
-                                        && let Some(connection) =
-                                            Connection::accept(&nic, iph, tcp_h, data)?
-                                    {
-                                        vacant_entry.insert(connection);
-                                        pending.push_back(q);
-
-                                        drop(lock);
+                                Entry::Vacant(_) => {
+                                    let accepted = on_listen(cm, nic, q, &iph, tcp_h, data)?;
+
+                                    drop(lock);
+                                    if accepted {
                                         ih.pending_var.notify_all();
                                     }
                                 }
@@ -129,11 +543,29 @@ fn packet_loop(ih: InterfaceHandle, nic: SyncDevice) -> std::io::Result<()> {
                             continue;
                         }
                     }
+                } else if iph.payload_ip_number() == IpNumber::UDP && !iph.is_fragmenting_payload()
+                {
+                    let received = udp::on_packet(&mut ih.manager.lock().unwrap(), &iph);
+                    if received {
+                        ih.rcv_var.notify_all();
+                    }
+                } else if matches!(
+                    iph.payload_ip_number(),
+                    IpNumber::ICMP | IpNumber::IPV6_ICMP
+                ) && !iph.is_fragmenting_payload()
+                {
+                    let mut lock = ih.manager.lock().unwrap();
+                    let cm = &mut *lock;
+                    let answered = cm.icmp.on_packet(nic, &iph, &cm.addrs)?;
+                    drop(lock);
+                    if answered {
+                        ih.ping_var.notify_all();
+                    }
                 }
             }
 
             Err(_) => {
-                // eprintln!("Failed to parse IPv4 header: {:?}", e);
+                // eprintln!("Failed to parse IP header: {:?}", e);
                 continue;
             }
         }
@@ -142,26 +574,156 @@ fn packet_loop(ih: InterfaceHandle, nic: SyncDevice) -> std::io::Result<()> {
 
 impl Interface {
     pub fn new() -> io::Result<Self> {
-        let nic = DeviceBuilder::new()
+        InterfaceBuilder::new()
             .name("tun0")
-            .ipv4(Ipv4Addr::new(192, 168, 0, 1), 24, None)
-            .build_sync()?;
+            .ipv4(Ipv4Addr::new(192, 168, 0, 1), 24)
+            .ipv6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1), 64)
+            .build()
+    }
+
+    pub fn builder() -> InterfaceBuilder {
+        InterfaceBuilder::new()
+    }
+}
+
+impl<D: Device> Interface<D> {
+    /// Run the stack on `nic`, answering to `addr` and `addr6`.
+    pub fn with_device(nic: D, addr: Ipv4Addr, addr6: Ipv6Addr) -> io::Result<Self> {
+        InterfaceBuilder::new()
+            .ipv4(addr, 32)
+            .ipv6(addr6, 128)
+            .build_with_device(nic)
+    }
+
+    /// Congestion control for connections created from now on, built from the sender MSS.
+    pub fn set_congestion_control<F>(&mut self, congestion: F)
+    where
+        F: Fn(usize) -> Box<dyn CongestionControl> + Send + Sync + 'static,
+    {
+        let mut cm = self.ih.as_mut().unwrap().manager.lock().unwrap();
+        cm.congestion = Arc::new(congestion);
+    }
 
-        let ih: InterfaceHandle = Arc::default();
-        let jh = {
-            let handle = ih.clone();
-            thread::spawn(move || packet_loop(handle, nic))
+    pub fn connect(&mut self, addr: impl Into<SocketAddr>) -> io::Result<TcpStream> {
+        self.connect_inner(addr.into(), None)
+    }
+
+    pub fn connect_timeout(
+        &mut self,
+        addr: impl Into<SocketAddr>,
+        timeout: Duration,
+    ) -> io::Result<TcpStream> {
+        self.connect_inner(addr.into(), Some(timeout))
+    }
+
+    fn connect_inner(
+        &mut self,
+        addr: SocketAddr,
+        timeout: Option<Duration>,
+    ) -> io::Result<TcpStream> {
+        let deadline = timeout.and_then(deadline);
+        let h = self.ih.as_ref().unwrap();
+        let mut cm = h.manager.lock().unwrap();
+        let local = local_ip(&cm.addrs, addr.ip())?;
+
+        let port = cm.ephemeral_port(local, addr)?;
+        let quad = Quad {
+            src: addr,
+            dst: SocketAddr::new(local, port),
         };
+        let mut conn = Connection::connect(quad.dst, quad.src, cm.config, &cm.congestion);
+        conn.owned = true;
+        cm.connection.insert(quad, conn);
 
-        Ok(Self {
-            ih: Some(ih),
-            jh: Some(jh),
-        })
+        loop {
+            let conn = cm.stream(&quad)?;
+
+            if !conn.is_connecting() {
+                if conn.is_closed() {
+                    let kind = conn.error.unwrap_or(io::ErrorKind::ConnectionRefused);
+                    cm.connection.remove(&quad);
+                    return Err(io::Error::new(kind, "connection failed"));
+                }
+
+                return TcpStream::new(quad, h.clone(), conn);
+            }
+
+            cm = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        cm.connection.remove(&quad);
+                        return Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "connection timed out",
+                        ));
+                    }
+                    h.est_var.wait_timeout(cm, deadline - now).unwrap().0
+                }
+                None => h.est_var.wait(cm).unwrap(),
+            };
+        }
+    }
+
+    /// Send an ICMP echo request to `addr` and wait for the reply, returns the round-trip time.
+    pub fn ping(
+        &mut self,
+        addr: impl Into<IpAddr>,
+        payload: &[u8],
+        timeout: Duration,
+    ) -> io::Result<Duration> {
+        let deadline = deadline(timeout);
+        let addr = addr.into();
+        let h = self.ih.as_ref().unwrap();
+        let mut cm = h.manager.lock().unwrap();
+        if cm.terminate {
+            return Err(shut_down_error());
+        }
+        let local = local_ip(&cm.addrs, addr)?;
+        let mtu = cm.config.mtu;
+        let seq = cm.icmp.request(local, addr, payload, mtu)?;
+
+        loop {
+            if cm.terminate {
+                return Err(shut_down_error());
+            }
+            if let Some(rtt) = cm.icmp.rtt(seq) {
+                cm.icmp.remove(seq);
+                return Ok(rtt);
+            }
+
+            cm = match wait_until(&h.ping_var, cm, deadline) {
+                Ok(cm) => cm,
+                Err(e) => {
+                    h.manager.lock().unwrap().icmp.remove(seq);
+                    return Err(e);
+                }
+            };
+        }
+    }
+
+    /// Counters of the SYNs received by our listeners.
+    pub fn syn_stats(&self) -> SynStats {
+        self.ih.as_ref().unwrap().manager.lock().unwrap().syn_stats
+    }
+
+    /// Bind a UDP socket to `port`, or to an ephemeral port if it is 0.
+    pub fn bind_udp(&mut self, port: u16) -> io::Result<UdpSocket> {
+        UdpSocket::bind(self.ih.as_ref().unwrap(), port)
     }
 
     pub fn bind(&mut self, port: u16) -> io::Result<TcpListener> {
+        self.bind_with_backlog(port, DEFAULT_BACKLOG)
+    }
+
+    /// Listen on `port`, with at most `backlog` connections in the handshake or
+    /// waiting for accept.
+    pub fn bind_with_backlog(&mut self, port: u16, backlog: usize) -> io::Result<TcpListener> {
         let mut ih = self.ih.as_mut().unwrap().manager.lock().unwrap();
-        match ih.pending.entry(port) {
+        if ih.terminate {
+            return Err(shut_down_error());
+        }
+        match ih.listeners.entry(port) {
             Entry::Occupied(_) => {
                 return Err(io::Error::new(
                     io::ErrorKind::AddrInUse,
@@ -169,13 +731,27 @@ impl Interface {
                 ));
             }
             Entry::Vacant(vacant_entry) => {
-                vacant_entry.insert(VecDeque::new());
+                vacant_entry.insert(Listener {
+                    syn_queue: HashSet::new(),
+                    pending: VecDeque::new(),
+                    backlog,
+                    overflow: ListenOverflow::default(),
+                    syn_cookies: true,
+                    waker: None,
+                    nonblocking: false,
+                    #[cfg(unix)]
+                    event: Arc::new(Event::new()?),
+                });
             }
         }
+        #[cfg(unix)]
+        let event = ih.listeners[&port].event.clone();
         drop(ih);
         Ok(TcpListener {
             port,
             h: self.ih.as_ref().unwrap().clone(),
+            #[cfg(unix)]
+            event,
         })
     }
 }
@@ -183,32 +759,147 @@ impl Interface {
 pub struct TcpStream {
     quad: Quad,
     h: InterfaceHandle,
+    #[cfg(unix)]
+    event: Arc<Event>,
 }
 
 impl TcpStream {
-    pub fn shutdown(&self) -> std::io::Result<()> {
+    /// Hand `conn` over to a new stream.
+    fn new(quad: Quad, h: InterfaceHandle, conn: &mut Connection) -> io::Result<Self> {
+        #[cfg(unix)]
+        let event = {
+            let event = Arc::new(Event::new()?);
+            event.update(&conn.availability());
+            conn.event = Some(event.clone());
+            event
+        };
+        conn.owned = true;
+
+        Ok(TcpStream {
+            quad,
+            h,
+            #[cfg(unix)]
+            event,
+        })
+    }
+
+    /// Address of the remote end of this connection.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.quad.src)
+    }
+
+    /// Address of the local end of this connection.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.quad.dst)
+    }
+
+    /// Number of retransmissions of a segment before the connection is aborted.
+    pub fn set_max_retries(&self, retries: u32) -> std::io::Result<()> {
         let mut cm = self.h.manager.lock().unwrap();
-        let c = cm.connection.get_mut(&self.quad).ok_or_else(|| {
-            io::Error::new(
-                io::ErrorKind::ConnectionAborted,
-                "stream was terminated unexpectedly",
-            )
-        })?;
+        cm.stream(&self.quad)?.max_retries = retries;
+        Ok(())
+    }
 
-        c.close()
+    /// Replace the congestion control of this connection.
+    pub fn set_congestion_control(
+        &self,
+        congestion: Box<dyn CongestionControl>,
+    ) -> std::io::Result<()> {
+        let mut cm = self.h.manager.lock().unwrap();
+        cm.stream(&self.quad)?.congestion = congestion;
+        Ok(())
+    }
+
+    /// How long a read waits for data before failing with `TimedOut`, `None` waits forever.
+    ///
+    /// A zero duration is rejected, like by `std::net::TcpStream`.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        check_timeout(timeout)?;
+        let mut cm = self.h.manager.lock().unwrap();
+        cm.stream(&self.quad)?.read_timeout = timeout;
+        Ok(())
+    }
+
+    /// How long a write or flush waits for buffer space or acknowledgements before
+    /// failing with `TimedOut`, `None` waits forever.
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        check_timeout(timeout)?;
+        let mut cm = self.h.manager.lock().unwrap();
+        cm.stream(&self.quad)?.write_timeout = timeout;
+        Ok(())
+    }
+
+    pub fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        let mut cm = self.h.manager.lock().unwrap();
+        Ok(cm.stream(&self.quad)?.read_timeout)
+    }
+
+    pub fn write_timeout(&self) -> io::Result<Option<Duration>> {
+        let mut cm = self.h.manager.lock().unwrap();
+        Ok(cm.stream(&self.quad)?.write_timeout)
+    }
+
+    /// Make reads, writes and flushes fail with `WouldBlock` instead of waiting.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        let mut cm = self.h.manager.lock().unwrap();
+        cm.stream(&self.quad)?.nonblocking = nonblocking;
+        Ok(())
+    }
+
+    /// Probe the peer once the connection is idle, `None` turns keepalive off.
+    ///
+    /// Once `probes` probes go unanswered the connection is aborted, and reads and
+    /// writes fail with `TimedOut`.
+    pub fn set_keepalive(&self, keepalive: Option<Keepalive>) -> io::Result<()> {
+        if let Some(k) = keepalive
+            && (k.idle.is_zero() || k.interval.is_zero() || k.probes == 0)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "keepalive times and probes must not be 0",
+            ));
+        }
+        let mut cm = self.h.manager.lock().unwrap();
+        cm.stream(&self.quad)?.keepalive = keepalive;
+        Ok(())
+    }
+
+    pub fn keepalive(&self) -> io::Result<Option<Keepalive>> {
+        let mut cm = self.h.manager.lock().unwrap();
+        Ok(cm.stream(&self.quad)?.keepalive)
+    }
+
+    /// What the stream is ready for right now.
+    pub fn readiness(&self) -> io::Result<Available> {
+        let mut cm = self.h.manager.lock().unwrap();
+        Ok(cm.stream(&self.quad)?.availability())
+    }
+
+    /// Readiness that makes the descriptor of the stream readable.
+    ///
+    /// `READ`, `HANGUP` and `ERROR` by default, add `WRITE` to wait for room in the send
+    /// buffer.
+    #[cfg(unix)]
+    pub fn set_interest(&self, interest: Available) -> io::Result<()> {
+        self.event.set_interest(interest);
+        let mut cm = self.h.manager.lock().unwrap();
+        cm.stream(&self.quad)?.update_event();
+        Ok(())
+    }
+
+    pub fn shutdown(&self) -> std::io::Result<()> {
+        let mut cm = self.h.manager.lock().unwrap();
+        cm.stream(&self.quad)?.close()
     }
 }
 
 impl Drop for TcpStream {
     fn drop(&mut self) {
         let mut cm = self.h.manager.lock().unwrap();
-        if cm
-            .connection
-            .get(&self.quad)
-            .expect("connection closed before drop")
-            .is_rcv_closed()
-        {
-            cm.connection.remove(&self.quad);
+        if let Some(c) = cm.connection.get_mut(&self.quad) {
+            // The packet loop finishes the close and reaps the connection
+            c.owned = false;
+            let _ = c.close();
         }
     }
 }
@@ -216,32 +907,17 @@ impl Drop for TcpStream {
 impl Read for TcpStream {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         let mut ih = self.h.manager.lock().unwrap();
+        let deadline = ih.stream(&self.quad)?.read_timeout.and_then(deadline);
         loop {
-            let conn = ih.connection.get_mut(&self.quad).ok_or_else(|| {
-                io::Error::new(
-                    io::ErrorKind::ConnectionAborted,
-                    "stream was terminated unexpectedly",
-                )
-            })?;
-
-            if conn.is_rcv_closed() && conn.incomming.is_empty() {
-                return Ok(0);
+            let conn = ih.stream(&self.quad)?;
+            if let Some(result) = try_read(conn, buf) {
+                return result;
             }
-
-            if !conn.incomming.is_empty() {
-                let mut nread = 0;
-                let (head, tail) = conn.incomming.as_slices();
-                let hread = std::cmp::min(head.len(), buf.len());
-                buf[..hread].copy_from_slice(&head[..hread]);
-                nread += hread;
-                let tread = std::cmp::min(tail.len(), buf.len() - nread);
-                buf[hread..][..tread].copy_from_slice(&tail[..tread]);
-                nread += tread;
-                drop(conn.incomming.drain(..nread));
-                return Ok(nread);
+            if conn.nonblocking {
+                return Err(io::ErrorKind::WouldBlock.into());
             }
 
-            ih = self.h.rcv_var.wait(ih).unwrap();
+            ih = wait_until(&self.h.rcv_var, ih, deadline)?;
         }
     }
 }
@@ -249,60 +925,136 @@ impl Read for TcpStream {
 impl Write for TcpStream {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         let mut ih = self.h.manager.lock().unwrap();
-        let conn = ih.connection.get_mut(&self.quad).ok_or_else(|| {
-            io::Error::new(
-                io::ErrorKind::ConnectionAborted,
-                "stream was terminated unexpectedly",
-            )
-        })?;
+        let deadline = ih.stream(&self.quad)?.write_timeout.and_then(deadline);
+        loop {
+            let conn = ih.stream(&self.quad)?;
+            if let Some(result) = try_write(conn, buf) {
+                return result;
+            }
+            if conn.nonblocking {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
 
-        if conn.unacked.len() >= SENDQUEUE_SIZE {
-            // TODO: block
-            return Err(io::Error::new(
-                io::ErrorKind::WouldBlock,
-                "too many bytes buffered",
-            ));
+            ih = wait_until(&self.h.snd_var, ih, deadline)?;
         }
-
-        let nwrite = std::cmp::min(buf.len(), SENDQUEUE_SIZE - conn.unacked.len());
-        conn.unacked.extend(&buf[..nwrite]);
-
-        // TODO: wake up writer
-
-        Ok(nwrite)
     }
 
     fn flush(&mut self) -> std::io::Result<()> {
         let mut ih = self.h.manager.lock().unwrap();
-        let conn = ih.connection.get_mut(&self.quad).ok_or_else(|| {
-            io::Error::new(
-                io::ErrorKind::ConnectionAborted,
-                "stream was terminated unexpectedly",
-            )
-        })?;
+        let deadline = ih.stream(&self.quad)?.write_timeout.and_then(deadline);
+        loop {
+            let conn = ih.stream(&self.quad)?;
+            if let Some(result) = try_flush(conn) {
+                return result;
+            }
+            if conn.nonblocking {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
 
-        if conn.unacked.is_empty() {
-            Ok(())
-        } else {
-            unimplemented!()
+            ih = wait_until(&self.h.snd_var, ih, deadline)?;
         }
     }
 }
 
+fn check_timeout(timeout: Option<Duration>) -> io::Result<()> {
+    if timeout == Some(Duration::ZERO) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "cannot set a 0 duration timeout",
+        ));
+    }
+    Ok(())
+}
+
+/// Read buffered data, `None` if the caller has to wait for more.
+fn try_read(conn: &mut Connection, buf: &mut [u8]) -> Option<io::Result<usize>> {
+    if let Some(kind) = conn.error
+        && conn.incomming.is_empty()
+    {
+        return Some(Err(kind.into()));
+    }
+
+    if conn.is_rcv_closed() && conn.incomming.is_empty() {
+        return Some(Ok(0));
+    }
+
+    if conn.incomming.is_empty() {
+        return None;
+    }
+
+    let mut nread = 0;
+    let (head, tail) = conn.incomming.as_slices();
+    let hread = std::cmp::min(head.len(), buf.len());
+    buf[..hread].copy_from_slice(&head[..hread]);
+    nread += hread;
+    let tread = std::cmp::min(tail.len(), buf.len() - nread);
+    buf[hread..][..tread].copy_from_slice(&tail[..tread]);
+    nread += tread;
+    drop(conn.incomming.drain(..nread));
+    conn.update_event();
+    Some(Ok(nread))
+}
+
+/// Queue data for sending, `None` if the caller has to wait for space.
+fn try_write(conn: &mut Connection, buf: &[u8]) -> Option<io::Result<usize>> {
+    if let Some(kind) = conn.error {
+        return Some(Err(kind.into()));
+    }
+
+    if conn.is_snd_closed() {
+        return Some(Err(io::Error::new(
+            io::ErrorKind::BrokenPipe,
+            "stream was shut down for writing",
+        )));
+    }
+
+    let send_buffer = conn.config.send_buffer;
+    if conn.unacked.len() >= send_buffer {
+        return None;
+    }
+
+    let nwrite = std::cmp::min(buf.len(), send_buffer - conn.unacked.len());
+    conn.unacked.extend(&buf[..nwrite]);
+    conn.update_event();
+    Some(Ok(nwrite))
+}
+
+/// `None` while sent data is still unacknowledged.
+fn try_flush(conn: &Connection) -> Option<io::Result<()>> {
+    if let Some(kind) = conn.error {
+        return Some(Err(kind.into()));
+    }
+
+    if conn.unacked.is_empty() {
+        return Some(Ok(()));
+    }
+
+    if conn.is_closed() {
+        return Some(Err(io::Error::new(
+            io::ErrorKind::ConnectionAborted,
+            "stream closed with unacknowledged data",
+        )));
+    }
+
+    None
+}
+
 pub struct TcpListener {
     port: u16,
     h: InterfaceHandle,
+    #[cfg(unix)]
+    event: Arc<Event>,
 }
 
 impl Drop for TcpListener {
     fn drop(&mut self) {
         let mut cm = self.h.manager.lock().unwrap();
-        let pending = cm
-            .pending
+        let listener = cm
+            .listeners
             .remove(&self.port)
             .expect("port closed while listener still active");
 
-        for quad in pending {
+        for quad in listener.syn_queue.into_iter().chain(listener.pending) {
             cm.connection
                 .get_mut(&quad)
                 .and_then(|con| con.close().ok());
@@ -312,16 +1064,65 @@ impl Drop for TcpListener {
 
 impl TcpListener {
     pub fn accept(&mut self) -> io::Result<TcpStream> {
+        self.accept_inner(None)
+    }
+
+    /// Accept a connection, failing with `TimedOut` if none arrives within `timeout`.
+    pub fn accept_timeout(&mut self, timeout: Duration) -> io::Result<TcpStream> {
+        self.accept_inner(deadline(timeout))
+    }
+
+    fn accept_inner(&mut self, deadline: Option<Instant>) -> io::Result<TcpStream> {
         let mut ih = self.h.manager.lock().unwrap();
         loop {
-            if let Some(quad) = ih.pending.get_mut(&self.port).unwrap().pop_front() {
-                return Ok(TcpStream {
-                    quad,
-                    h: self.h.clone(),
-                });
+            if let Some(stream) = self.try_accept(&mut ih)? {
+                return Ok(stream);
+            }
+            if ih.listeners[&self.port].nonblocking {
+                return Err(io::ErrorKind::WouldBlock.into());
             }
 
-            ih = self.h.pending_var.wait(ih).unwrap();
+            ih = wait_until(&self.h.pending_var, ih, deadline)?;
+        }
+    }
+
+    /// Make accept fail with `WouldBlock` instead of waiting.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        let mut cm = self.h.manager.lock().unwrap();
+        cm.listeners.get_mut(&self.port).unwrap().nonblocking = nonblocking;
+        Ok(())
+    }
+
+    /// Answer SYNs with SYN cookies while the SYN queue is full, on by default.
+    pub fn set_syn_cookies(&self, enabled: bool) -> io::Result<()> {
+        let mut cm = self.h.manager.lock().unwrap();
+        cm.listeners.get_mut(&self.port).unwrap().syn_cookies = enabled;
+        Ok(())
+    }
+
+    /// What to do with new connections while the backlog is full.
+    pub fn set_overflow(&self, overflow: ListenOverflow) -> io::Result<()> {
+        let mut cm = self.h.manager.lock().unwrap();
+        cm.listeners.get_mut(&self.port).unwrap().overflow = overflow;
+        Ok(())
+    }
+
+    /// Take the next queued connection, if any.
+    fn try_accept(&self, cm: &mut ConnectionManager) -> io::Result<Option<TcpStream>> {
+        if cm.terminate {
+            return Err(shut_down_error());
+        }
+        let listener = cm.listeners.get_mut(&self.port).unwrap();
+        while let Some(quad) = listener.pending.pop_front() {
+            // Connections that were reset before being accepted may be reaped already
+            if let Some(c) = cm.connection.get_mut(&quad) {
+                #[cfg(unix)]
+                listener.event.update(&listener.availability());
+                return TcpStream::new(quad, self.h.clone(), c).map(Some);
+            }
         }
+        #[cfg(unix)]
+        listener.event.update(&listener.availability());
+        Ok(None)
     }
 }
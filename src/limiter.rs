@@ -0,0 +1,66 @@
+use std::{
+    hash::{BuildHasher, RandomState},
+    net::IpAddr,
+    time::Instant,
+};
+
+/// Buckets of a `SourceLimiter`
+const SOURCE_BUCKETS: usize = 1024;
+
+/// Token bucket allowing `rate` events per second, in bursts of up to `burst`.
+pub(crate) struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(rate: u32, burst: u32) -> Self {
+        TokenBucket {
+            rate: rate as f64,
+            burst: burst as f64,
+            tokens: burst as f64,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    /// Take a token, `false` if the event has to be dropped.
+    pub(crate) fn try_take(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        self.tokens = f64::min(self.burst, self.tokens + elapsed.as_secs_f64() * self.rate);
+        self.refilled_at = now;
+
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+/// Token buckets per source address.
+///
+/// Sources are hashed onto a fixed number of buckets, so a flood from spoofed addresses
+/// cannot grow it. Sources sharing a bucket share their limit.
+pub(crate) struct SourceLimiter {
+    key: RandomState,
+    buckets: Vec<TokenBucket>,
+}
+
+impl SourceLimiter {
+    pub(crate) fn new(rate: u32, burst: u32) -> Self {
+        SourceLimiter {
+            key: RandomState::new(),
+            buckets: (0..SOURCE_BUCKETS)
+                .map(|_| TokenBucket::new(rate, burst))
+                .collect(),
+        }
+    }
+
+    /// Take a token for `source`, `false` if the event has to be dropped.
+    pub(crate) fn try_take(&mut self, source: IpAddr, now: Instant) -> bool {
+        let index = self.key.hash_one(source) as usize % self.buckets.len();
+        self.buckets[index].try_take(now)
+    }
+}
@@ -1,21 +1,75 @@
+#[cfg(unix)]
+use crate::event::Event;
+use crate::{device::Device, limiter::TokenBucket};
 use bitflags::bitflags;
 use std::{
     collections::VecDeque,
     io::{self, Write},
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    task::Waker,
+    time::{Duration, Instant},
 };
-use tun_rs::SyncDevice;
 
-use etherparse::{IpNumber, Ipv4Header, Ipv4HeaderSlice, TcpHeader, TcpHeaderSlice};
+use etherparse::{
+    IpNumber, IpSlice, Ipv4Header, Ipv6Header, TcpHeader, TcpHeaderSlice, TcpOptionElement,
+};
+
+use crate::tcp::{
+    assembler::Assembler,
+    congestion::{CongestionControl, Recovery},
+    cookie::{CookieOptions, SynCookies},
+    rate::RateSampler,
+    sack::Scoreboard,
+};
+
+mod assembler;
+pub(crate) mod congestion;
+pub(crate) mod cookie;
+mod rate;
+mod sack;
+
+const INITIAL_RTO: Duration = Duration::from_secs(1);
+const MIN_RTO: Duration = Duration::from_secs(1);
+const MAX_RTO: Duration = Duration::from_secs(60);
+/// Clock granularity G from RFC 6298
+const CLOCK_GRANULARITY: Duration = Duration::from_millis(1);
+pub(crate) const DEFAULT_MAX_RETRIES: u32 = 15;
+/// Retransmissions of a SYN or SYN-ACK before the handshake is abandoned
+pub(crate) const DEFAULT_SYN_RETRIES: u32 = 5;
+const MAX_WSCALE: u8 = 14;
+/// TS.Recent is no longer trusted for PAWS after this long (RFC 7323, 5.5)
+const PAWS_IDLE: Duration = Duration::from_secs(24 * 24 * 60 * 60);
+/// MSS assumed when the peer does not send the option (RFC 9293, 3.7.1)
+const DEFAULT_MSS: usize = 536;
+/// Maximum segment lifetime
+const MSL: Duration = Duration::from_secs(30);
+const TIME_WAIT: Duration = MSL.saturating_mul(2);
+/// Time a dropped stream waits in FIN-WAIT-2 for the peer's FIN
+pub(crate) const DEFAULT_FIN_TIMEOUT: Duration = Duration::from_secs(60);
+const LAST_ACK_TIMEOUT: Duration = Duration::from_secs(60);
+/// Bytes of out-of-order data buffered per connection
+const REASSEMBLY_BUDGET: usize = 64 * 1024;
 
 bitflags! {
-    pub(crate) struct Available: u8 {
-        const READ  = 0b00000001;
+    /// Readiness of a socket
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Available: u8 {
+        /// data can be read, or the read would fail or return end of stream
+        const READ   = 0b00000001;
+        /// data can be queued for sending
+        const WRITE  = 0b00000010;
+        /// the peer will not send any more data
+        const HANGUP = 0b00000100;
+        /// the connection failed
+        const ERROR  = 0b00001000;
     }
 
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum State {
+    SynSent,
     SynRcv,
     Established,
     FinWait1,
@@ -30,7 +84,7 @@ enum State {
 impl State {
     fn is_synchronized(&self) -> bool {
         match self {
-            State::SynRcv => false,
+            State::SynSent | State::SynRcv => false,
             State::Established
             | State::FinWait1
             | State::FinWait2
@@ -47,13 +101,276 @@ pub struct Connection {
     state: State,
     send: SendSequenceSpace,
     recv: RecvSequenceSpace,
-    iph: Ipv4Header,
+    iph: IpHeader,
     tcp: TcpHeader,
 
     pub(crate) incomming: VecDeque<u8>,
     pub(crate) unacked: VecDeque<u8>,
+    out_of_order: Assembler,
 
     closed_at: Option<u32>,
+    pub(crate) error: Option<io::ErrorKind>,
+
+    pub(crate) config: Config,
+    /// sender maximum segment size
+    smss: usize,
+    /// whether window scaling was offered, and once synchronized, agreed on
+    window_scaling: bool,
+    /// set while timestamps are offered or in use
+    timestamps: Option<Timestamps>,
+    /// whether SACK was offered, and once synchronized, agreed on
+    sack_permitted: bool,
+    /// duplicate segment to report in a D-SACK block with the next ACK (RFC 2883)
+    dsack: Option<(u32, u32)>,
+    /// sequence number of the latest out-of-order segment, its block is reported first
+    last_out_of_order: Option<u32>,
+    scoreboard: Scoreboard,
+
+    timers: Timers,
+    pub(crate) max_retries: u32,
+    pub(crate) congestion: Box<dyn CongestionControl>,
+    congestion_factory: congestion::Factory,
+    recovery: Recovery,
+    rate: RateSampler,
+    /// whether a `TcpStream` refers to this connection
+    pub(crate) owned: bool,
+    /// tasks waiting for the stream to become readable or writable
+    pub(crate) read_waker: Option<Waker>,
+    pub(crate) write_waker: Option<Waker>,
+    /// whether stream operations fail with `WouldBlock` instead of waiting
+    pub(crate) nonblocking: bool,
+    /// how long blocking reads and writes or flushes wait before failing
+    pub(crate) read_timeout: Option<Duration>,
+    pub(crate) write_timeout: Option<Duration>,
+    /// probes an idle connection when set
+    pub(crate) keepalive: Option<Keepalive>,
+    #[cfg(unix)]
+    pub(crate) event: Option<Arc<Event>>,
+}
+
+/// Keepalive settings of a [`TcpStream`](crate::TcpStream) (RFC 9293, 3.8.4).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Keepalive {
+    /// how long the connection has to be idle before the first probe
+    pub idle: Duration,
+    /// time between unanswered probes
+    pub interval: Duration,
+    /// unanswered probes after which the connection is aborted
+    pub probes: u32,
+}
+
+impl Keepalive {
+    /// When probe number `probes` is due, `None` if that is too far out to ever happen
+    fn probe_at(&self, received_at: Instant, probes: u32) -> Option<Instant> {
+        let wait = self.idle.checked_add(self.interval.checked_mul(probes)?)?;
+        received_at.checked_add(wait)
+    }
+}
+
+/// Settings shared by the connections of an interface
+#[derive(Clone, Copy)]
+pub(crate) struct Config {
+    /// MTU of the interface
+    pub(crate) mtu: usize,
+    /// bytes the application may queue for sending
+    pub(crate) send_buffer: usize,
+    /// bytes buffered for the application to read, bounds the advertised window
+    pub(crate) recv_buffer: usize,
+    /// retransmissions of a SYN or SYN-ACK before the handshake is abandoned
+    pub(crate) syn_retries: u32,
+    /// time a dropped stream waits in FIN-WAIT-2 for the peer's FIN
+    pub(crate) fin_timeout: Duration,
+}
+
+/// Retransmission timer state as described in RFC 6298
+struct Timers {
+    /// end sequence number and send time of every segment transmitted once
+    send_times: VecDeque<(u32, Instant)>,
+    /// smoothed round-trip time
+    srtt: Option<Duration>,
+    /// round-trip time variation
+    rttvar: Duration,
+    /// retransmission timeout, including backoff
+    rto: Duration,
+    /// when the retransmission timer expires
+    expires_at: Option<Instant>,
+    /// consecutive retransmissions of the oldest unacknowledged segment
+    retries: u32,
+    /// state that times out and when, for TIME-WAIT, FIN-WAIT-2 and LAST-ACK
+    state_timer: Option<(State, Instant)>,
+    /// bytes that may go out now when pacing, refilled at the pacing rate
+    pacing_budget: f64,
+    /// when `pacing_budget` was last refilled
+    paced_at: Instant,
+    /// when the last acceptable segment arrived, for keepalive
+    received_at: Instant,
+    /// keepalive probes sent since then
+    probes: u32,
+}
+
+impl Default for Timers {
+    fn default() -> Self {
+        Timers {
+            send_times: VecDeque::new(),
+            srtt: None,
+            rttvar: Duration::ZERO,
+            rto: INITIAL_RTO,
+            expires_at: None,
+            retries: 0,
+            state_timer: None,
+            pacing_budget: 0.0,
+            paced_at: Instant::now(),
+            received_at: Instant::now(),
+            probes: 0,
+        }
+    }
+}
+
+impl Timers {
+    fn on_rtt_sample(&mut self, r: Duration) {
+        let srtt = match self.srtt {
+            None => {
+                self.rttvar = r / 2;
+                r
+            }
+            Some(srtt) => {
+                let delta = srtt.abs_diff(r);
+                self.rttvar = (self.rttvar * 3 + delta) / 4;
+                (srtt * 7 + r) / 8
+            }
+        };
+        self.srtt = Some(srtt);
+        self.rto =
+            (srtt + std::cmp::max(CLOCK_GRANULARITY, self.rttvar * 4)).clamp(MIN_RTO, MAX_RTO);
+    }
+}
+
+/// Options carried by a received segment
+#[derive(Default)]
+struct Options {
+    mss: Option<u16>,
+    wscale: Option<u8>,
+    /// TSval and TSecr
+    timestamp: Option<(u32, u32)>,
+    sack_permitted: bool,
+    /// SACK blocks as left and right edges
+    sack: Vec<(u32, u32)>,
+}
+
+impl Options {
+    fn parse(tcp_header: &TcpHeaderSlice) -> Self {
+        let mut options = Options::default();
+        for option in tcp_header.options_iterator().flatten() {
+            match option {
+                TcpOptionElement::MaximumSegmentSize(mss) => options.mss = Some(mss),
+                // RFC 7323, 2.3: shifts above 14 are treated as 14
+                TcpOptionElement::WindowScale(shift) => {
+                    options.wscale = Some(std::cmp::min(shift, MAX_WSCALE))
+                }
+                TcpOptionElement::Timestamp(tsval, tsecr) => {
+                    options.timestamp = Some((tsval, tsecr))
+                }
+                TcpOptionElement::SelectiveAcknowledgementPermitted => {
+                    options.sack_permitted = true
+                }
+                TcpOptionElement::SelectiveAcknowledgement(first, rest) => {
+                    options.sack.push(first);
+                    options.sack.extend(rest.into_iter().flatten());
+                }
+                _ => {}
+            }
+        }
+        options
+    }
+}
+
+/// Timestamps option state as described in RFC 7323
+struct Timestamps {
+    /// random offset of our clock
+    offset: u32,
+    /// start of our clock
+    epoch: Instant,
+    /// latest TSval received from the peer, echoed in TSecr
+    recent: u32,
+    /// when `recent` was updated
+    recent_at: Instant,
+    /// RCV.NXT acknowledged by the last segment we sent
+    last_ack_sent: u32,
+}
+
+impl Timestamps {
+    fn new(recent: u32) -> Self {
+        let now = Instant::now();
+        Timestamps {
+            offset: rand::random(),
+            epoch: now,
+            recent,
+            recent_at: now,
+            last_ack_sent: 0,
+        }
+    }
+
+    /// Our clock in milliseconds
+    fn now(&self) -> u32 {
+        self.offset
+            .wrapping_add(self.epoch.elapsed().as_millis() as u32)
+    }
+}
+
+/// IP header of outgoing segments
+#[derive(Clone)]
+enum IpHeader {
+    V4(Ipv4Header),
+    V6(Ipv6Header),
+}
+
+impl IpHeader {
+    fn new(local: IpAddr, remote: IpAddr) -> Self {
+        match (local, remote) {
+            (IpAddr::V4(local), IpAddr::V4(remote)) => IpHeader::V4(
+                Ipv4Header::new(0, 64, IpNumber::TCP, local.octets(), remote.octets()).unwrap(),
+            ),
+            (local, remote) => IpHeader::V6(Ipv6Header {
+                next_header: IpNumber::TCP,
+                hop_limit: 64,
+                source: to_ipv6(local).octets(),
+                destination: to_ipv6(remote).octets(),
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn header_len(&self) -> usize {
+        match self {
+            IpHeader::V4(iph) => iph.header_len(),
+            IpHeader::V6(iph) => iph.header_len(),
+        }
+    }
+
+    fn set_payload_len(&mut self, len: usize) {
+        match self {
+            IpHeader::V4(iph) => iph.set_payload_len(len).expect("Failed to set payload len"),
+            IpHeader::V6(iph) => iph
+                .set_payload_length(len)
+                .expect("Failed to set payload len"),
+        }
+    }
+
+    fn write<T: Write>(&self, writer: &mut T) -> io::Result<()> {
+        match self {
+            IpHeader::V4(iph) => iph.write(writer),
+            IpHeader::V6(iph) => iph.write(writer),
+        }
+    }
+
+    /// TCP checksum including the pseudo header of the address family
+    fn tcp_checksum(&self, tcp: &TcpHeader, payload: &[u8]) -> u16 {
+        match self {
+            IpHeader::V4(iph) => tcp.calc_checksum_ipv4(iph, payload),
+            IpHeader::V6(iph) => tcp.calc_checksum_ipv6(iph, payload),
+        }
+        .expect("failed to compute checksum")
+    }
 }
 
 struct SendSequenceSpace {
@@ -62,7 +379,9 @@ struct SendSequenceSpace {
     /// send next
     nxt: u32,
     /// send window
-    wnd: u16,
+    wnd: u32,
+    /// shift applied to windows advertised by the peer
+    wscale: u8,
     // /// send urgent pointer
     // up: bool,
     // /// segment sequence number used for last windows update
@@ -77,7 +396,9 @@ struct RecvSequenceSpace {
     /// receive next
     nxt: u32,
     /// receive winodw
-    wnd: u16,
+    wnd: u32,
+    /// shift applied to windows we advertise
+    wscale: u8,
     // /// receive urgent pointer
     // up: bool,
     // /// initial receive sequence number
@@ -85,25 +406,85 @@ struct RecvSequenceSpace {
 }
 
 impl Connection {
+    /// Whether the peer's FIN was received, or the connection is gone.
     pub fn is_rcv_closed(&self) -> bool {
-        matches!(self.state, State::TimeWait | State::Closed)
+        matches!(
+            self.state,
+            State::CloseWait | State::LastAck | State::Closing | State::TimeWait | State::Closed
+        )
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        matches!(self.state, State::Closed)
+    }
+
+    /// Whether the application may no longer queue data for sending.
+    pub(crate) fn is_snd_closed(&self) -> bool {
+        !matches!(
+            self.state,
+            State::SynSent | State::SynRcv | State::Established | State::CloseWait
+        )
     }
 
-    fn availability(&self) -> Available {
+    pub(crate) fn is_connecting(&self) -> bool {
+        matches!(self.state, State::SynSent | State::SynRcv)
+    }
+
+    pub(crate) fn availability(&self) -> Available {
         let mut available = Available::empty();
 
         if self.is_rcv_closed() || !self.incomming.is_empty() {
             available |= Available::READ;
         }
 
+        if self.is_snd_closed() || self.unacked.len() < self.config.send_buffer {
+            available |= Available::WRITE;
+        }
+
+        if matches!(
+            self.state,
+            State::CloseWait | State::LastAck | State::Closing | State::TimeWait | State::Closed
+        ) {
+            available |= Available::HANGUP;
+        }
+
+        if self.error.is_some() {
+            available |= Available::ERROR;
+        }
+
         available
     }
 
+    /// Signal the pollable descriptor of the stream, if it has one.
+    pub(crate) fn update_event(&self) {
+        #[cfg(unix)]
+        if let Some(event) = &self.event {
+            event.update(&self.availability());
+        }
+    }
+
+    /// Wake the tasks waiting for what became available.
+    pub(crate) fn wake(&mut self, available: &Available) {
+        self.update_event();
+        if available.contains(Available::READ)
+            && let Some(waker) = self.read_waker.take()
+        {
+            waker.wake();
+        }
+        if available.contains(Available::WRITE)
+            && let Some(waker) = self.write_waker.take()
+        {
+            waker.wake();
+        }
+    }
+
     pub fn accept(
-        nic: &SyncDevice,
-        iph: Ipv4HeaderSlice,
+        nic: &dyn Device,
+        iph: &IpSlice,
         tcp_header: TcpHeaderSlice,
         _payload: &[u8],
+        config: Config,
+        congestion: &congestion::Factory,
     ) -> Result<Option<Self>, std::io::Error> {
         // println!(
         //     "RST packet: {}:{} -> {}:{}, seq: {}, ack: {}, window: {}, payload length: {}",
@@ -116,30 +497,99 @@ impl Connection {
         //     tcp_header.window_size(),
         //     payload.len()
         // );
-        if !tcp_header.syn() {
-            // We only handle SYN packets in LISTEN state
+        if !tcp_header.syn() || tcp_header.ack() || tcp_header.rst() {
+            // We only handle SYN packets in LISTEN state, any ACK is bad
             return Ok(None);
         }
 
-        let iss = rand::random();
+        let options = Options::parse(&tcp_header);
+        let irs = tcp_header.sequence_number();
+        let mut c = Connection::passive(
+            iph,
+            &tcp_header,
+            irs,
+            &options,
+            rand::random(),
+            config,
+            congestion,
+        );
+        c.write(nic, c.send.nxt, 0)?;
+
+        Ok(Some(c))
+    }
+
+    /// Recreate the connection a SYN cookie was sent for from the ACK completing the
+    /// handshake, `None` if the ACK does not carry a valid cookie.
+    ///
+    /// The connection is left in SYN-RECEIVED, the ACK itself still has to be processed.
+    pub(crate) fn from_cookie(
+        iph: &IpSlice,
+        tcp_header: &TcpHeaderSlice,
+        config: Config,
+        congestion: &congestion::Factory,
+        cookies: &SynCookies,
+    ) -> Option<Self> {
+        let iss = tcp_header.acknowledgment_number().wrapping_sub(1);
+        let irs = tcp_header.sequence_number().wrapping_sub(1);
+        let remote = SocketAddr::new(iph.source_addr(), tcp_header.source_port());
+        let local = SocketAddr::new(iph.destination_addr(), tcp_header.destination_port());
+        let cookie = cookies.decode(remote, local, irs, iss)?;
+
+        let options = Options {
+            mss: Some(cookie.mss),
+            wscale: cookie.wscale,
+            sack_permitted: cookie.sack_permitted,
+            ..Default::default()
+        };
+        let mut c = Connection::passive(iph, tcp_header, irs, &options, iss, config, congestion);
+        // The SYN-ACK went out with the cookie
+        c.send.nxt = iss.wrapping_add(1);
+        // Unlike the SYN's, the window of the completing ACK is scaled
+        c.send.wnd = c.peer_window(tcp_header);
+        Some(c)
+    }
+
+    /// A connection in SYN-RECEIVED for a SYN from `iph` with sequence number `irs`.
+    fn passive(
+        iph: &IpSlice,
+        tcp_header: &TcpHeaderSlice,
+        irs: u32,
+        options: &Options,
+        iss: u32,
+        config: Config,
+        congestion: &congestion::Factory,
+    ) -> Self {
+        let iph = IpHeader::new(iph.destination_addr(), iph.source_addr());
+        let smss = std::cmp::min(
+            options.mss.map_or(DEFAULT_MSS, usize::from),
+            max_segment_size(config.mtu, &iph),
+        );
+
         let mut c = Connection {
             state: State::SynRcv,
             send: SendSequenceSpace {
                 // iss,
                 una: iss,
                 nxt: iss,
-                wnd: tcp_header.window_size(),
+                // The window of a SYN is never scaled
+                wnd: tcp_header.window_size() as u32,
+                wscale: options.wscale.unwrap_or(0),
                 // up: false,
                 // wl1: 0,
                 // wl2: 0,
             },
             recv: RecvSequenceSpace {
                 // irs: tcp_header.sequence_number(),
-                nxt: tcp_header.sequence_number() + 1,
-                wnd: tcp_header.window_size(),
+                nxt: irs.wrapping_add(1),
+                wnd: tcp_header.window_size() as u32,
+                wscale: if options.wscale.is_some() {
+                    receive_window_scale(config.recv_buffer)
+                } else {
+                    0
+                },
                 // up: false,
             },
-            iph: Ipv4Header::new(0, 64, IpNumber::TCP, iph.destination(), iph.source()).unwrap(),
+            iph,
             tcp: TcpHeader::new(
                 tcp_header.destination_port(),
                 tcp_header.source_port(),
@@ -148,22 +598,204 @@ impl Connection {
             ),
             incomming: Default::default(),
             unacked: Default::default(),
+            out_of_order: Assembler::new(REASSEMBLY_BUDGET),
             closed_at: None,
+            error: None,
+            config,
+            smss,
+            window_scaling: options.wscale.is_some(),
+            timestamps: options.timestamp.map(|(tsval, _)| Timestamps::new(tsval)),
+            sack_permitted: options.sack_permitted,
+            dsack: None,
+            last_out_of_order: None,
+            scoreboard: Scoreboard::new(iss),
+            timers: Timers::default(),
+            max_retries: DEFAULT_MAX_RETRIES,
+            congestion: congestion(smss),
+            congestion_factory: congestion.clone(),
+            recovery: Recovery::new(iss),
+            rate: RateSampler::new(),
+            owned: false,
+            read_waker: None,
+            write_waker: None,
+            nonblocking: false,
+            read_timeout: None,
+            write_timeout: None,
+            keepalive: None,
+            #[cfg(unix)]
+            event: None,
         };
 
-        c.tcp.syn = true;
         c.tcp.ack = true;
-        c.write(nic, c.send.nxt, 0)?;
+        c
+    }
 
-        Ok(Some(c))
+    /// Create a connection in SYN-SENT, the SYN itself goes out on the next tick.
+    pub(crate) fn connect(
+        local: SocketAddr,
+        remote: SocketAddr,
+        config: Config,
+        congestion: &congestion::Factory,
+    ) -> Self {
+        let iph = IpHeader::new(local.ip(), remote.ip());
+        // Until the SYN-ACK tells us the peer's MSS
+        let smss = max_segment_size(config.mtu, &iph);
+        let iss = rand::random();
+        Connection {
+            state: State::SynSent,
+            send: SendSequenceSpace {
+                una: iss,
+                nxt: iss,
+                wnd: 0,
+                wscale: 0,
+            },
+            recv: RecvSequenceSpace {
+                nxt: 0,
+                wnd: u16::MAX as u32,
+                wscale: receive_window_scale(config.recv_buffer),
+            },
+            iph,
+            tcp: TcpHeader::new(local.port(), remote.port(), iss, u16::MAX),
+            incomming: Default::default(),
+            unacked: Default::default(),
+            out_of_order: Assembler::new(REASSEMBLY_BUDGET),
+            closed_at: None,
+            error: None,
+            config,
+            smss,
+            window_scaling: true,
+            timestamps: Some(Timestamps::new(0)),
+            sack_permitted: true,
+            dsack: None,
+            last_out_of_order: None,
+            scoreboard: Scoreboard::new(iss),
+            timers: Timers::default(),
+            max_retries: DEFAULT_MAX_RETRIES,
+            congestion: congestion(smss),
+            congestion_factory: congestion.clone(),
+            recovery: Recovery::new(iss),
+            rate: RateSampler::new(),
+            owned: false,
+            read_waker: None,
+            write_waker: None,
+            nonblocking: false,
+            read_timeout: None,
+            write_timeout: None,
+            keepalive: None,
+            #[cfg(unix)]
+            event: None,
+        }
+    }
+
+    fn on_syn_sent(
+        &mut self,
+        nic: &dyn Device,
+        resets: &mut TokenBucket,
+        tcp_header: TcpHeaderSlice,
+    ) -> Result<Available, std::io::Error> {
+        let iss = self.send.una;
+        let ack = tcp_header.acknowledgment_number();
+
+        if tcp_header.ack() {
+            // SEG.ACK =< ISS or SEG.ACK > SND.NXT
+            if !between_wrapping(iss, ack, self.send.nxt.wrapping_add(1)) {
+                if !tcp_header.rst() && resets.try_take(Instant::now()) {
+                    self.send_reset(nic, ack, None)?;
+                }
+                return Ok(self.availability());
+            }
+            if tcp_header.rst() {
+                // The peer refused the connection
+                self.state = State::Closed;
+                self.error = Some(io::ErrorKind::ConnectionRefused);
+                return Ok(self.availability());
+            }
+        } else if tcp_header.rst() {
+            return Ok(self.availability());
+        }
+
+        if !tcp_header.syn() {
+            return Ok(self.availability());
+        }
+
+        self.recv.nxt = tcp_header.sequence_number().wrapping_add(1);
+        self.send.wnd = tcp_header.window_size() as u32;
+        self.tcp.ack = true;
+
+        let options = Options::parse(&tcp_header);
+        match options.wscale {
+            Some(shift) => self.send.wscale = shift,
+            None => {
+                // Both sides have to send the option for scaling to be used
+                self.window_scaling = false;
+                self.recv.wscale = 0;
+            }
+        }
+        let smss = std::cmp::min(options.mss.map_or(DEFAULT_MSS, usize::from), self.smss);
+        if smss != self.smss {
+            self.smss = smss;
+            self.congestion = (self.congestion_factory)(smss);
+        }
+
+        match (&mut self.timestamps, options.timestamp) {
+            (Some(ts), Some((tsval, _))) => {
+                ts.recent = tsval;
+                ts.recent_at = Instant::now();
+            }
+            _ => self.timestamps = None,
+        }
+        self.sack_permitted &= options.sack_permitted;
+
+        if tcp_header.ack() {
+            // SYN-ACK for our SYN
+            self.on_acked(ack, options.timestamp.map(|(_, tsecr)| tsecr));
+            self.state = State::Established;
+            self.write(nic, self.send.nxt, 0)?;
+        } else {
+            // Simultaneous open, answer with a SYN-ACK reusing our ISS
+            self.state = State::SynRcv;
+            self.write(nic, self.send.una, 0)?;
+        }
+
+        Ok(self.availability())
     }
     pub(crate) fn on_packet(
         &mut self,
-        nic: &SyncDevice,
-        _iph: Ipv4HeaderSlice,
+        nic: &dyn Device,
+        resets: &mut TokenBucket,
+        _iph: &IpSlice,
         tcp_header: TcpHeaderSlice,
         payload: &[u8],
     ) -> Result<Available, std::io::Error> {
+        if let State::SynSent = self.state {
+            return self.on_syn_sent(nic, resets, tcp_header);
+        }
+
+        // A retransmitted SYN means our SYN-ACK was lost, send it again
+        if let State::SynRcv = self.state
+            && tcp_header.syn()
+            && !tcp_header.ack()
+            && !tcp_header.rst()
+            && tcp_header.sequence_number().wrapping_add(1) == self.recv.nxt
+        {
+            self.write(nic, self.send.una, 0)?;
+            return Ok(self.availability());
+        }
+
+        let options = Options::parse(&tcp_header);
+        let tsecr = options.timestamp.map(|(_, tsecr)| tsecr);
+
+        // PAWS (RFC 7323, 5.3): reject old duplicates whose timestamp went backwards
+        if let Some(ts) = &self.timestamps
+            && let Some((tsval, _)) = options.timestamp
+            && !tcp_header.rst()
+            && wrapping_lt(tsval, ts.recent)
+            && ts.recent_at.elapsed() < PAWS_IDLE
+        {
+            self.write(nic, self.send.nxt, 0)?;
+            return Ok(self.availability());
+        }
+
         // Sequence number validation according to RFC 793
         let seqn = tcp_header.sequence_number();
         let mut seg_len = payload.len() as u32;
@@ -174,7 +806,7 @@ impl Connection {
             seg_len += 1;
         }
 
-        let rcv_wnd = self.recv.wnd as u32;
+        let rcv_wnd = self.recv.wnd;
         let is_valid = if seg_len == 0 {
             // Zero length segment
             if rcv_wnd == 0 {
@@ -208,13 +840,40 @@ impl Connection {
         };
 
         if !is_valid {
-            // Send ACK for invalid sequence number
-            if tcp_header.ack() {
+            if self.state == State::TimeWait && tcp_header.fin() {
+                // Retransmitted FIN, our last ACK got lost
+                self.timers.state_timer = Some((State::TimeWait, Instant::now() + TIME_WAIT));
+            }
+
+            // Data entirely below RCV.NXT is a duplicate
+            if self.sack_permitted
+                && !payload.is_empty()
+                && !wrapping_lt(self.recv.nxt, seqn.wrapping_add(payload.len() as u32))
+            {
+                self.dsack = Some((seqn, seqn.wrapping_add(payload.len() as u32)));
+            }
+
+            // Send ACK for invalid sequence number, a SYN outside the window included
+            if tcp_header.ack() || tcp_header.syn() {
                 self.write(nic, self.send.nxt, 0)?;
             }
             return Ok(self.availability());
         }
 
+        // Any acceptable segment shows the peer is alive
+        self.timers.received_at = Instant::now();
+        self.timers.probes = 0;
+
+        // RFC 7323, 4.3: remember the TSval to echo if the segment covers Last.ACK.sent
+        if let Some(ts) = &mut self.timestamps
+            && let Some((tsval, _)) = options.timestamp
+            && !wrapping_lt(tsval, ts.recent)
+            && !wrapping_lt(ts.last_ack_sent, seqn)
+        {
+            ts.recent = tsval;
+            ts.recent_at = Instant::now();
+        }
+
         // Process RST
         if tcp_header.rst() {
             match self.state {
@@ -224,26 +883,30 @@ impl Connection {
                     return Ok(self.availability());
                 }
                 State::Established | State::FinWait1 | State::FinWait2 | State::CloseWait => {
-                    // Close the connection
+                    // Close the connection, pending reads and writes fail
                     self.state = State::Closed;
+                    self.error = Some(io::ErrorKind::ConnectionReset);
+                    self.timers.expires_at = None;
                     return Ok(self.availability());
                 }
                 _ => {}
             }
         }
 
-        // Process SYN in synchronized states
-        if tcp_header.syn() && self.state.is_synchronized() {
-            // This is an error - send RST and close
+        // Process SYN in synchronized states and SYN-RCVD, a retransmitted SYN was
+        // answered above
+        if tcp_header.syn() && (self.state.is_synchronized() || self.state == State::SynRcv) {
+            // This is an error - send RST and close, a passive open returns to LISTEN
+            if resets.try_take(Instant::now()) {
+                self.send_reset(nic, self.send.nxt, None)?;
+            }
             self.state = State::Closed;
+            self.error = Some(io::ErrorKind::ConnectionReset);
+            self.timers.expires_at = None;
             return Ok(self.availability());
         }
 
         if !tcp_header.ack() {
-            if tcp_header.syn() {
-                assert!(payload.is_empty());
-                self.recv.nxt = seqn.wrapping_add(seg_len);
-            }
             return Ok(self.availability());
         }
 
@@ -251,16 +914,16 @@ impl Connection {
         let ack = tcp_header.acknowledgment_number();
 
         if let State::SynRcv = self.state {
-            if between_wrapping(
-                self.send.una.wrapping_sub(1),
-                ack,
-                self.send.nxt.wrapping_add(1),
-            ) {
+            // SND.UNA < SEG.ACK =< SND.NXT, an ACK of the ISS does not cover the SYN
+            if between_wrapping(self.send.una, ack, self.send.nxt.wrapping_add(1)) {
                 // Update send.una to acknowledge the SYN
-                self.send.una = ack;
+                self.on_acked(ack, tsecr);
                 self.state = State::Established;
             } else {
-                // TODO: RST
+                // <SEQ=SEG.ACK><CTL=RST>, the connection stays half-open
+                if resets.try_take(Instant::now()) {
+                    self.send_reset(nic, ack, None)?;
+                }
                 return Ok(self.availability());
             }
         }
@@ -272,16 +935,78 @@ impl Connection {
         | State::CloseWait
         | State::Closing
         | State::LastAck = self.state
-            && between_wrapping(self.send.una, ack, self.send.nxt.wrapping_add(1))
         {
-            let data_acked = ack.wrapping_sub(self.send.una) as usize;
-            self.send.una = ack;
-            // Remove acknowledged bytes from unacked queue
-            if data_acked > 0 && data_acked <= self.unacked.len() {
-                drop(self.unacked.drain(..data_acked));
+            let flight = self.send.nxt.wrapping_sub(self.send.una) as usize;
+            let (una, wnd) = (self.send.una, self.send.wnd);
+            if self.sack_permitted {
+                self.scoreboard
+                    .update(self.send.una, self.send.nxt, &options.sack);
+            }
+            if between_wrapping(self.send.una, ack, self.send.nxt.wrapping_add(1)) {
+                let data_acked = ack.wrapping_sub(self.send.una) as usize;
+                self.on_acked(ack, tsecr);
+                self.scoreboard.on_ack(ack);
+                let inflight = self.send.nxt.wrapping_sub(self.send.una) as usize;
+                if let Some(sample) = self.rate.on_ack(ack, data_acked, inflight) {
+                    self.congestion.on_rate_sample(&sample);
+                }
+                // Remove acknowledged bytes from unacked queue, an acked FIN has no byte there
+                let acked_bytes = std::cmp::min(data_acked, self.unacked.len());
+                drop(self.unacked.drain(..acked_bytes));
+                // Update send window
+                self.send.wnd = self.peer_window(&tcp_header);
+
+                let in_recovery = self.recovery.in_recovery();
+                if self.recovery.on_ack(ack, data_acked, self.smss) {
+                    // Partial ACK during fast recovery, the next hole was lost as well.
+                    // With SACK the holes are retransmitted by `send_pending` instead.
+                    if !self.sack_permitted {
+                        self.retransmit(nic)?;
+                    }
+                } else if !in_recovery {
+                    self.congestion.on_ack(data_acked, self.timers.srtt);
+                }
+            } else if ack == self.send.una
+                && flight > 0
+                && payload.is_empty()
+                && !tcp_header.fin()
+                && self.peer_window(&tcp_header) == self.send.wnd
+                && self.recovery.on_dup_ack(ack, self.send.nxt, self.smss)
+            {
+                // Fast retransmit
+                self.congestion.on_loss(flight);
+                self.scoreboard.on_recovery(self.send.una);
+                self.retransmit(nic)?;
+            } else if ack == self.send.una {
+                // Window update, or the answer to a zero window probe
+                self.send.wnd = self.peer_window(&tcp_header);
+                if flight == 0 {
+                    // The peer is alive, only its window holds data back
+                    self.timers.retries = 0;
+                    if self.send.wnd > 0 {
+                        // Nothing is in flight, so this was the persist timer
+                        self.timers.expires_at = None;
+                    }
+                }
+            }
+
+            if self.sack_permitted
+                && !self.recovery.in_recovery()
+                && self
+                    .scoreboard
+                    .is_lost(self.send.una, self.send.nxt, self.smss)
+                && self.recovery.on_sack_loss(self.send.una, self.send.nxt)
+            {
+                // Enough was SACKed above SND.UNA to consider it lost (RFC 6675, 5)
+                self.congestion.on_loss(flight);
+                self.scoreboard.on_recovery(self.send.una);
+                self.retransmit(nic)?;
+            }
+
+            // The ACK clocks out whatever the window allows now
+            if self.send.una != una || self.send.wnd > wnd {
+                self.send_pending(nic)?;
             }
-            // Update send window
-            self.send.wnd = tcp_header.window_size();
         }
 
         // Check if our FIN has been acknowledged
@@ -317,12 +1042,17 @@ impl Connection {
                         // In-order data, accept it
                         self.incomming.extend(payload);
                         self.recv.nxt = self.recv.nxt.wrapping_add(payload.len() as u32);
+                        self.reassemble();
                         // Send ACK for received data
                         self.write(nic, self.send.nxt, 0)?;
                     } else if wrapping_lt(seqn, self.recv.nxt) {
                         // Old/duplicate data
                         // Check if there's any new data in this segment
                         let already_received = self.recv.nxt.wrapping_sub(seqn) as usize;
+                        if self.sack_permitted {
+                            let duplicate = std::cmp::min(already_received, payload.len());
+                            self.dsack = Some((seqn, seqn.wrapping_add(duplicate as u32)));
+                        }
                         if already_received < payload.len() {
                             // Part of the segment is new data
                             self.incomming.extend(&payload[already_received..]);
@@ -330,11 +1060,28 @@ impl Connection {
                                 .recv
                                 .nxt
                                 .wrapping_add((payload.len() - already_received) as u32);
+                            self.reassemble();
                         }
                         // Send ACK (could be duplicate ACK if all data was old)
                         self.write(nic, self.send.nxt, 0)?;
+                    } else {
+                        // Future data, keep the part inside the receive window until the
+                        // hole in front of it is filled
+                        let offset = seqn.wrapping_sub(self.recv.nxt) as usize;
+                        let room = (self.recv.wnd as usize).saturating_sub(offset);
+                        let len = std::cmp::min(payload.len(), room);
+                        if self.sack_permitted && self.out_of_order.contains(seqn, len) {
+                            self.dsack = Some((seqn, seqn.wrapping_add(len as u32)));
+                        }
+                        if self
+                            .out_of_order
+                            .insert(self.recv.nxt, seqn, &payload[..len])
+                        {
+                            self.last_out_of_order = Some(seqn);
+                        }
+                        // Duplicate ACK tells the peer where the hole is
+                        self.write(nic, self.send.nxt, 0)?;
                     }
-                    // else: future data, drop it (we don't have out-of-order buffering)
                 }
                 State::CloseWait | State::Closing | State::LastAck | State::TimeWait => {
                     // Ignore data in these states
@@ -343,8 +1090,9 @@ impl Connection {
             }
         }
 
-        // Process FIN (must be after payload processing)
-        if tcp_header.fin() {
+        // Process FIN (must be after payload processing), a FIN past a hole is
+        // ignored and will be retransmitted by the peer
+        if tcp_header.fin() && seqn.wrapping_add(payload.len() as u32) == self.recv.nxt {
             match self.state {
                 State::Established => {
                     // Peer is closing - advance recv.nxt for the FIN
@@ -379,7 +1127,9 @@ impl Connection {
                     // FIN already processed
                 }
                 State::TimeWait => {
-                    // Restart TIME-WAIT timer (not implemented yet)
+                    // Restart TIME-WAIT timer
+                    self.write(nic, self.send.nxt, 0)?;
+                    self.timers.state_timer = Some((State::TimeWait, Instant::now() + TIME_WAIT));
                 }
                 _ => {}
             }
@@ -388,17 +1138,43 @@ impl Connection {
         Ok(self.availability())
     }
 
-    fn write(&mut self, nic: &SyncDevice, seq: u32, mut limit: usize) -> std::io::Result<usize> {
-        let mut buf = [0u8; 1500];
+    /// Send a RST for this connection.
+    fn send_reset(&self, nic: &dyn Device, seq: u32, ack: Option<u32>) -> io::Result<()> {
+        send_reset(
+            nic,
+            self.iph.clone(),
+            (self.tcp.source_port, self.tcp.destination_port),
+            seq,
+            ack,
+        )
+    }
+
+    fn write(&mut self, nic: &dyn Device, seq: u32, mut limit: usize) -> std::io::Result<usize> {
+        let mut buf = vec![0u8; self.config.mtu];
 
         self.tcp.sequence_number = seq;
         self.tcp.acknowledgment_number = self.recv.nxt;
+        // The SYN occupies the first sequence number until it is acknowledged
+        self.tcp.syn = self.is_connecting() && seq == self.send.una;
+        if self.tcp.syn {
+            limit = 0;
+        }
+        self.set_options();
+
+        // The peer's MSS covers our options as well
+        let max_payload = self
+            .smss
+            .saturating_sub(self.tcp.header_len() - TcpHeader::MIN_LEN);
+        limit = std::cmp::min(limit, max_payload);
 
-        // Update receive window based on available buffer space
-        let available = (u16::MAX as usize).saturating_sub(self.incomming.len());
-        self.tcp.window_size = available.min(u16::MAX as usize) as u16;
+        // Update receive window based on available buffer space, the window of a SYN
+        // is never scaled
+        let shift = if self.tcp.syn { 0 } else { self.recv.wscale };
+        self.tcp.window_size = self.window_size(shift);
+        self.recv.wnd = (self.tcp.window_size as u32) << shift;
 
-        let mut offset = seq.wrapping_sub(self.send.una) as usize;
+        let mut offset =
+            std::cmp::min(seq.wrapping_sub(self.send.una) as usize, self.unacked.len());
 
         // eprintln!(
         //     "DEBUG write(): seq={}, send.una={}, send.nxt={}, offset={}, unacked.len={}, limit={}",
@@ -432,9 +1208,7 @@ impl Connection {
             buf.len(),
             self.tcp.header_len() + self.iph.header_len() + max_data,
         );
-        self.iph
-            .set_payload_len(size - self.iph.header_len())
-            .expect("Failed to set payload len");
+        self.iph.set_payload_len(size - self.iph.header_len());
 
         let buf_len = buf.len();
         let mut unwritten = &mut buf[..];
@@ -461,10 +1235,13 @@ impl Connection {
         let payload_end_at = buf_len - unwritten.len();
         assert!(payload_bytes == payload_end_at - tcp_header_end_at);
 
+        self.tcp.fin = self
+            .closed_at
+            .is_some_and(|closed_at| seq.wrapping_add(payload_bytes as u32) == closed_at);
+
         self.tcp.checksum = self
-            .tcp
-            .calc_checksum_ipv4(&self.iph, &buf[tcp_header_end_at..payload_end_at])
-            .expect("failed to compute checksum");
+            .iph
+            .tcp_checksum(&self.tcp, &buf[tcp_header_end_at..payload_end_at]);
 
         let mut tcp_header_buf = &mut buf[ip_header_end_at..tcp_header_end_at];
         self.tcp.write(&mut tcp_header_buf)?;
@@ -479,10 +1256,24 @@ impl Connection {
             self.tcp.fin = false;
         }
 
+        if next_seq != seq {
+            let now = Instant::now();
+            if seq == self.send.nxt {
+                self.timers.send_times.push_back((next_seq, now));
+                let inflight = self.send.nxt.wrapping_sub(self.send.una) as usize;
+                self.rate.on_send(next_seq, inflight, now);
+            } else {
+                // Karn's algorithm: never sample the RTT of retransmitted data
+                self.timers.send_times.clear();
+            }
+            if self.timers.expires_at.is_none() {
+                self.timers.expires_at = Some(now + self.timers.rto);
+            }
+        }
+
         if wrapping_lt(self.send.nxt, next_seq) {
             self.send.nxt = next_seq;
         }
-        // self.timers.send_times.insert(seq, time::Instant::now());
 
         nic.send(&buf[..payload_end_at])?;
         // eprintln!(
@@ -492,39 +1283,382 @@ impl Connection {
         Ok(payload_bytes)
     }
 
-    pub(crate) fn on_tick(&mut self, nic: &SyncDevice) -> std::io::Result<()> {
-        if !self.unacked.is_empty() {
-            self.tcp.psh = true;
-            // Calculate how much we can send based on peer's window and data in flight
-            let inflight = self.send.nxt.wrapping_sub(self.send.una) as usize;
-            let available_window = (self.send.wnd as usize).saturating_sub(inflight);
-            let size = std::cmp::min(self.unacked.len(), available_window);
-            if size > 0 {
-                self.write(nic, self.send.una, size)?;
+    /// Window advertised by a segment from the peer, in bytes
+    fn peer_window(&self, tcp_header: &TcpHeaderSlice) -> u32 {
+        (tcp_header.window_size() as u32) << self.send.wscale
+    }
+
+    fn set_options(&mut self) {
+        let mut options = vec![];
+        if self.tcp.syn {
+            options.push(TcpOptionElement::MaximumSegmentSize(max_segment_size(
+                self.config.mtu,
+                &self.iph,
+            ) as u16));
+            if self.window_scaling {
+                options.push(TcpOptionElement::WindowScale(self.recv.wscale));
+            }
+            if self.sack_permitted {
+                options.push(TcpOptionElement::SelectiveAcknowledgementPermitted);
+            }
+        } else if self.sack_permitted
+            && let Some(sack) = self.sack_option()
+        {
+            options.push(sack);
+        }
+        if let Some(ts) = &mut self.timestamps {
+            // Nothing to echo yet in our own SYN
+            let tsecr = if self.state == State::SynSent {
+                0
+            } else {
+                ts.recent
+            };
+            options.push(TcpOptionElement::Timestamp(ts.now(), tsecr));
+            ts.last_ack_sent = self.recv.nxt;
+        }
+        self.tcp
+            .set_options(&options)
+            .expect("options do not fit into the TCP header");
+    }
+
+    /// SACK blocks for an ACK: a pending D-SACK first, then the block holding the latest
+    /// out-of-order segment, then the rest (RFC 2018, 4).
+    fn sack_option(&mut self) -> Option<TcpOptionElement> {
+        // Timestamps leave room for three blocks in the 40 option bytes
+        let max_blocks = if self.timestamps.is_some() { 3 } else { 4 };
+        let last = self.last_out_of_order;
+        let contains_last = |&(start, end): &(u32, u32)| {
+            last.is_some_and(|seq| !wrapping_lt(seq, start) && wrapping_lt(seq, end))
+        };
+
+        let mut blocks: Vec<(u32, u32)> = self.dsack.take().into_iter().collect();
+        blocks.extend(self.out_of_order.blocks().filter(contains_last));
+        blocks.extend(self.out_of_order.blocks().filter(|b| !contains_last(b)));
+        blocks.truncate(max_blocks);
+
+        let (&first, rest) = blocks.split_first()?;
+        let mut others = [None; 3];
+        for (slot, block) in others.iter_mut().zip(rest) {
+            *slot = Some(*block);
+        }
+        Some(TcpOptionElement::SelectiveAcknowledgement(first, others))
+    }
+
+    /// Advance SND.UNA, take an RTT sample and restart the retransmission timer.
+    ///
+    /// `tsecr` is the timestamp echoed by the ACK, if it carried one.
+    fn on_acked(&mut self, ack: u32, tsecr: Option<u32>) {
+        if !wrapping_lt(self.send.una, ack) {
+            return;
+        }
+        self.send.una = ack;
+
+        let now = Instant::now();
+        let mut sent_at = None;
+        while let Some(&(end, at)) = self.timers.send_times.front()
+            && !wrapping_lt(ack, end)
+        {
+            sent_at = Some(at);
+            self.timers.send_times.pop_front();
+        }
+
+        if let Some(ts) = &self.timestamps
+            && let Some(tsecr) = tsecr
+        {
+            // The echoed timestamp measures retransmitted segments as well (RFC 7323, 4.1)
+            let rtt = ts.now().wrapping_sub(tsecr);
+            self.timers.on_rtt_sample(Duration::from_millis(rtt as u64));
+        } else if let Some(sent_at) = sent_at {
+            self.timers.on_rtt_sample(now - sent_at);
+        }
+
+        self.timers.retries = 0;
+        self.timers.expires_at = if self.send.una == self.send.nxt {
+            None
+        } else {
+            Some(now + self.timers.rto)
+        };
+    }
+
+    /// Move buffered out-of-order data that now continues at RCV.NXT into `incomming`.
+    fn reassemble(&mut self) {
+        while let Some(data) = self.out_of_order.pop(self.recv.nxt) {
+            self.recv.nxt = self.recv.nxt.wrapping_add(data.len() as u32);
+            self.incomming.extend(data);
+        }
+    }
+
+    fn can_send_data(&self) -> bool {
+        matches!(
+            self.state,
+            State::Established | State::FinWait1 | State::CloseWait | State::LastAck
+        )
+    }
+
+    /// Send queued data that has not been transmitted yet, followed by our FIN.
+    ///
+    /// Fills the usable window with MSS-sized segments unless pacing holds them back.
+    fn send_pending(&mut self, nic: &dyn Device) -> std::io::Result<()> {
+        if !self.can_send_data() {
+            return Ok(());
+        }
+
+        // Refill the pacing budget for the time since the last call, a tick's worth of
+        // segments may go out back to back but no more
+        let pacing_rate = self.congestion.pacing_rate().filter(|rate| *rate > 0.0);
+        if let Some(rate) = pacing_rate {
+            let now = Instant::now();
+            let burst = f64::max(
+                rate * (2 * crate::TICK_INTERVAL).as_secs_f64(),
+                (2 * self.smss) as f64,
+            );
+            let elapsed = now.saturating_duration_since(self.timers.paced_at);
+            self.timers.pacing_budget = f64::min(
+                burst,
+                self.timers.pacing_budget + rate * elapsed.as_secs_f64(),
+            );
+            self.timers.paced_at = now;
+        }
+
+        loop {
+            let outstanding = self.send.nxt.wrapping_sub(self.send.una) as usize;
+            let unsent = self.unacked.len().saturating_sub(outstanding);
+            // With SACK, and after a timeout, recovery is driven by the pipe estimate instead
+            // of window inflation
+            let sack_recovery = (self.sack_permitted && self.recovery.in_recovery())
+                || self.scoreboard.in_loss(self.send.una);
+            let (inflight, cwnd) = if sack_recovery {
+                let pipe = self
+                    .scoreboard
+                    .pipe(self.send.una, self.send.nxt, self.smss);
+                (pipe, self.congestion.window())
+            } else {
+                (
+                    outstanding,
+                    self.congestion.window() + self.recovery.inflation(),
+                )
+            };
+            let window = std::cmp::min(self.send.wnd as usize, cwnd);
+            let available_window = window.saturating_sub(inflight);
+
+            // Holes the peer is missing go before new data
+            if sack_recovery
+                && available_window > 0
+                && let Some((seq, len)) =
+                    self.scoreboard
+                        .next_seg(self.send.una, self.send.nxt, self.smss)
+            {
+                if self.resend(nic, seq, std::cmp::min(len, available_window))? == 0 {
+                    return Ok(());
+                }
+                continue;
+            }
+
+            let size = std::cmp::min(unsent, available_window);
+
+            if size == 0 {
+                if unsent > 0 && self.send.wnd == 0 && outstanding == 0 {
+                    // Persist timer, probe the zero window until it opens (RFC 9293, 3.8.6.1)
+                    self.timers
+                        .expires_at
+                        .get_or_insert_with(|| Instant::now() + self.timers.rto);
+                }
+                if unsent == 0 {
+                    if inflight < cwnd {
+                        self.rate.on_app_limited(inflight);
+                    }
+                    if self.closed_at == Some(self.send.nxt) {
+                        self.write(nic, self.send.nxt, 0)?;
+                    }
+                }
+                return Ok(());
+            }
+
+            if pacing_rate.is_some() && self.timers.pacing_budget <= 0.0 {
+                // Paced out, wait for a later tick
+                return Ok(());
             }
+
+            self.tcp.psh = true;
+            let sent = self.write(nic, self.send.nxt, size)?;
             self.tcp.psh = false;
+            if sent == 0 {
+                return Ok(());
+            }
+
+            if pacing_rate.is_some() {
+                self.timers.pacing_budget -= sent as f64;
+            }
         }
+    }
 
-        if let State::CloseWait = self.state {
-            self.tcp.fin = true;
-            self.closed_at = Some(self.send.una.wrapping_add(self.unacked.len() as u32));
-            self.write(nic, self.send.una, 0)?;
-            self.state = State::LastAck;
+    /// Resend the oldest unacknowledged segment.
+    fn retransmit(&mut self, nic: &dyn Device) -> std::io::Result<()> {
+        // Probe with at least one byte when the peer closed its window
+        let size = std::cmp::min(self.unacked.len(), std::cmp::max(self.send.wnd as usize, 1));
+        self.resend(nic, self.send.una, size)?;
+        Ok(())
+    }
+
+    /// Retransmit up to `limit` bytes at `seq` and record it on the scoreboard, returns
+    /// the sequence space covered.
+    fn resend(&mut self, nic: &dyn Device, seq: u32, limit: usize) -> io::Result<u32> {
+        let sent = self.write(nic, seq, limit)?;
+        let mut end = seq.wrapping_add(sent as u32);
+        if self.closed_at == Some(end) && wrapping_lt(end, self.send.nxt) {
+            // The FIN went along
+            end = end.wrapping_add(1);
+        }
+        self.scoreboard.on_retransmit(end);
+        Ok(end.wrapping_sub(seq))
+    }
+
+    pub(crate) fn on_tick(&mut self, nic: &dyn Device) -> std::io::Result<Available> {
+        if let State::Closed = self.state {
+            return Ok(self.availability());
+        }
+
+        let now = Instant::now();
+        // A stream that is still owned may receive for as long as the peer sends, like
+        // Linux only orphaned connections time out in FIN-WAIT-2
+        let timeout = match self.state {
+            State::TimeWait => Some(TIME_WAIT),
+            State::FinWait2 if !self.owned => Some(self.config.fin_timeout),
+            State::LastAck => Some(LAST_ACK_TIMEOUT),
+            _ => None,
+        };
+        match (timeout, self.timers.state_timer) {
+            (Some(_), Some((state, at))) if state == self.state => {
+                if now >= at {
+                    if let State::FinWait2 = self.state {
+                        // The peer may still be sending, make sure it learns of the abort
+                        self.send_reset(nic, self.send.nxt, None)?;
+                        self.error = Some(io::ErrorKind::TimedOut);
+                    }
+                    self.state = State::Closed;
+                    self.timers.state_timer = None;
+                    self.timers.expires_at = None;
+                    return Ok(self.availability());
+                }
+            }
+            (Some(timeout), _) => {
+                self.timers.state_timer = now.checked_add(timeout).map(|at| (self.state, at));
+            }
+            (None, _) => self.timers.state_timer = None,
         }
 
+        if let Some(expires_at) = self.timers.expires_at
+            && now >= expires_at
+        {
+            let max_retries = if self.is_connecting() {
+                self.config.syn_retries
+            } else {
+                self.max_retries
+            };
+            if self.timers.retries >= max_retries {
+                // Give up on the peer
+                self.state = State::Closed;
+                self.error = Some(io::ErrorKind::TimedOut);
+                self.timers.expires_at = None;
+                return Ok(self.availability());
+            }
+
+            self.timers.retries += 1;
+            self.timers.rto = std::cmp::min(self.timers.rto * 2, MAX_RTO);
+            self.timers.expires_at = Some(now + self.timers.rto);
+            if self.send.una == self.send.nxt && !self.is_connecting() {
+                // Nothing was lost, the peer's window is closed. <SEQ=SND.UNA-1> makes it
+                // answer with the current window.
+                self.write(nic, self.send.una.wrapping_sub(1), 0)?;
+            } else {
+                let flight = self.send.nxt.wrapping_sub(self.send.una) as usize;
+                self.recovery.on_rto(self.send.nxt);
+                // Go back to SND.UNA, `send_pending` resends the rest as the window opens
+                self.scoreboard.on_rto(self.send.una, self.send.nxt);
+                self.rate.on_rto();
+                self.congestion.on_rto(flight);
+                self.retransmit(nic)?;
+            }
+        }
+
+        // Probe an idle connection with nothing in flight, retransmissions cover the rest
+        if let Some(keepalive) = self.keepalive
+            && let State::Established = self.state
+            && self.send.una == self.send.nxt
+            && keepalive
+                .probe_at(self.timers.received_at, self.timers.probes)
+                .is_some_and(|at| now >= at)
+        {
+            if self.timers.probes >= keepalive.probes {
+                self.send_reset(nic, self.send.nxt, None)?;
+                self.state = State::Closed;
+                self.error = Some(io::ErrorKind::TimedOut);
+                self.timers.expires_at = None;
+                return Ok(self.availability());
+            }
+            // <SEQ=SND.NXT-1>, the peer answers with an ACK
+            self.write(nic, self.send.nxt.wrapping_sub(1), 0)?;
+            self.timers.probes += 1;
+        }
+
+        if let State::SynSent = self.state {
+            if self.send.una == self.send.nxt {
+                self.write(nic, self.send.una, 0)?;
+            }
+            return Ok(self.availability());
+        }
+
+        self.send_pending(nic)?;
+        self.send_window_update(nic)?;
+
+        Ok(self.availability())
+    }
+
+    /// Window field for the free space in the receive buffer, scaled down by `shift`
+    fn window_size(&self, shift: u8) -> u16 {
+        let available = self.config.recv_buffer.saturating_sub(self.incomming.len());
+        (available >> shift).min(u16::MAX as usize) as u16
+    }
+
+    /// Advertise the room reads made in the receive buffer once it is worth it, the peer
+    /// may be waiting for it with a zero window (RFC 9293, 3.8.6.2.2).
+    fn send_window_update(&mut self, nic: &dyn Device) -> io::Result<()> {
+        if !matches!(
+            self.state,
+            State::Established | State::FinWait1 | State::FinWait2
+        ) {
+            return Ok(());
+        }
+        let window = (self.window_size(self.recv.wscale) as u32) << self.recv.wscale;
+        let threshold = std::cmp::min(
+            self.config.recv_buffer / 2,
+            max_segment_size(self.config.mtu, &self.iph),
+        );
+        if window.saturating_sub(self.recv.wnd) as usize >= threshold {
+            self.write(nic, self.send.nxt, 0)?;
+        }
         Ok(())
     }
 
     pub(crate) fn close(&mut self) -> std::io::Result<()> {
         match self.state {
+            State::SynSent => {
+                self.state = State::Closed;
+            }
+
             State::SynRcv | State::Established => {
-                self.tcp.fin = true;
-                self.closed_at = Some(self.send.una.wrapping_add(self.unacked.len() as u32));
+                // The FIN follows the SYN if that has not been acknowledged yet
+                let syn = if self.is_connecting() { 1 } else { 0 };
+                self.closed_at = Some(
+                    self.send
+                        .una
+                        .wrapping_add(self.unacked.len() as u32)
+                        .wrapping_add(syn),
+                );
                 self.state = State::FinWait1;
             }
 
             State::CloseWait => {
-                self.tcp.fin = true;
                 self.closed_at = Some(self.send.una.wrapping_add(self.unacked.len() as u32));
                 self.state = State::LastAck;
             }
@@ -542,6 +1676,133 @@ impl Connection {
     }
 }
 
+/// Smallest shift that lets the whole receive buffer be advertised
+fn receive_window_scale(recv_buffer: usize) -> u8 {
+    let mut shift = 0;
+    while shift < MAX_WSCALE && recv_buffer >> shift > u16::MAX as usize {
+        shift += 1;
+    }
+    shift
+}
+
+/// Largest segment payload the interface MTU allows, without IP or TCP options
+fn max_segment_size(mtu: usize, iph: &IpHeader) -> usize {
+    mtu - iph.header_len() - TcpHeader::MIN_LEN
+}
+
+/// Answer a segment that belongs to no connection with a RST (RFC 9293, 3.10.7.1).
+///
+/// RSTs are never answered, and no more than `resets` allows are sent.
+pub(crate) fn reset(
+    nic: &dyn Device,
+    resets: &mut TokenBucket,
+    iph: &IpSlice,
+    tcp_header: &TcpHeaderSlice,
+    payload: &[u8],
+) -> io::Result<()> {
+    if tcp_header.rst() || !resets.try_take(Instant::now()) {
+        return Ok(());
+    }
+
+    let ports = (tcp_header.destination_port(), tcp_header.source_port());
+    let ip = IpHeader::new(iph.destination_addr(), iph.source_addr());
+    if tcp_header.ack() {
+        // <SEQ=SEG.ACK><CTL=RST>
+        send_reset(nic, ip, ports, tcp_header.acknowledgment_number(), None)
+    } else {
+        // <SEQ=0><ACK=SEG.SEQ+SEG.LEN><CTL=RST,ACK>
+        let seg_len = payload.len() as u32 + tcp_header.syn() as u32 + tcp_header.fin() as u32;
+        let ack = tcp_header.sequence_number().wrapping_add(seg_len);
+        send_reset(nic, ip, ports, 0, Some(ack))
+    }
+}
+
+/// Answer a SYN with a SYN-ACK whose ISS is a SYN cookie, keeping no state
+/// (RFC 4987, 3.6).
+///
+/// Timestamps are not encoded in the cookie, so they are not offered.
+pub(crate) fn send_cookie(
+    nic: &dyn Device,
+    iph: &IpSlice,
+    tcp_header: &TcpHeaderSlice,
+    config: Config,
+    cookies: &mut SynCookies,
+) -> io::Result<()> {
+    let options = Options::parse(tcp_header);
+    let irs = tcp_header.sequence_number();
+    let remote = SocketAddr::new(iph.source_addr(), tcp_header.source_port());
+    let local = SocketAddr::new(iph.destination_addr(), tcp_header.destination_port());
+    let (iss, _) = cookies.encode(
+        remote,
+        local,
+        irs,
+        &CookieOptions {
+            mss: options.mss.unwrap_or(DEFAULT_MSS as u16),
+            wscale: options.wscale,
+            sack_permitted: options.sack_permitted,
+        },
+    );
+
+    // The window of a SYN is never scaled
+    let window = config.recv_buffer.min(u16::MAX as usize) as u16;
+    let ip = IpHeader::new(local.ip(), remote.ip());
+    let mut tcp = TcpHeader::new(local.port(), remote.port(), iss, window);
+    tcp.syn = true;
+    tcp.ack = true;
+    tcp.acknowledgment_number = irs.wrapping_add(1);
+    let mut elements = vec![TcpOptionElement::MaximumSegmentSize(
+        max_segment_size(config.mtu, &ip) as u16,
+    )];
+    if options.wscale.is_some() {
+        elements.push(TcpOptionElement::WindowScale(receive_window_scale(
+            config.recv_buffer,
+        )));
+    }
+    if options.sack_permitted {
+        elements.push(TcpOptionElement::SelectiveAcknowledgementPermitted);
+    }
+    tcp.set_options(&elements)
+        .expect("options do not fit into the TCP header");
+    send_segment(nic, ip, tcp)
+}
+
+/// Send a bare RST segment, acknowledging `ack` if set.
+fn send_reset(
+    nic: &dyn Device,
+    iph: IpHeader,
+    (src_port, dst_port): (u16, u16),
+    seq: u32,
+    ack: Option<u32>,
+) -> io::Result<()> {
+    let mut tcp = TcpHeader::new(src_port, dst_port, seq, 0);
+    tcp.rst = true;
+    if let Some(ack) = ack {
+        tcp.ack = true;
+        tcp.acknowledgment_number = ack;
+    }
+    send_segment(nic, iph, tcp)
+}
+
+/// Send a segment without payload.
+fn send_segment(nic: &dyn Device, mut iph: IpHeader, mut tcp: TcpHeader) -> io::Result<()> {
+    iph.set_payload_len(tcp.header_len());
+    tcp.checksum = iph.tcp_checksum(&tcp, &[]);
+
+    let mut buf = Vec::with_capacity(iph.header_len() + tcp.header_len());
+    iph.write(&mut buf)?;
+    tcp.write(&mut buf)?;
+    nic.send(&buf)?;
+    Ok(())
+}
+
+/// IPv4 addresses are only mixed with IPv6 ones in their mapped form
+pub(crate) fn to_ipv6(addr: IpAddr) -> Ipv6Addr {
+    match addr {
+        IpAddr::V4(addr) => addr.to_ipv6_mapped(),
+        IpAddr::V6(addr) => addr,
+    }
+}
+
 fn wrapping_lt(lhs: u32, rhs: u32) -> bool {
     // lhs < rhs in modular arithmetic
     lhs.wrapping_sub(rhs) > (1 << 31)
@@ -0,0 +1,171 @@
+use std::collections::VecDeque;
+
+/// Out-of-order segments waiting for the hole in front of them to be filled.
+///
+/// Segments are kept sorted by sequence number, never overlap and never touch,
+/// overlapping or adjacent data is merged into a single segment on insert.
+pub(crate) struct Assembler {
+    segments: VecDeque<(u32, Vec<u8>)>,
+    /// bytes currently held
+    len: usize,
+    /// maximum number of bytes held
+    capacity: usize,
+}
+
+impl Assembler {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Assembler {
+            segments: VecDeque::new(),
+            len: 0,
+            capacity,
+        }
+    }
+
+    /// Queue `data` starting at `seq`, which must lie ahead of `nxt`.
+    ///
+    /// Returns false if the segment was dropped because it would exceed the budget.
+    pub(crate) fn insert(&mut self, nxt: u32, seq: u32, data: &[u8]) -> bool {
+        if data.is_empty() {
+            return true;
+        }
+
+        let rel = |seq: u32| seq.wrapping_sub(nxt) as usize;
+        let start = rel(seq);
+        let end = start + data.len();
+
+        // Segments overlapping or adjacent to [start, end)
+        let lo = self
+            .segments
+            .partition_point(|(seq, data)| rel(*seq) + data.len() < start);
+        let hi = self.segments.partition_point(|(seq, _)| rel(*seq) <= end);
+
+        if lo == hi {
+            if self.len + data.len() > self.capacity {
+                return false;
+            }
+            self.segments.insert(lo, (seq, data.to_vec()));
+            self.len += data.len();
+            return true;
+        }
+
+        let merged_start = std::cmp::min(start, rel(self.segments[lo].0));
+        let (last_seq, last_data) = &self.segments[hi - 1];
+        let merged_end = std::cmp::max(end, rel(*last_seq) + last_data.len());
+        let replaced: usize = self.segments.range(lo..hi).map(|(_, d)| d.len()).sum();
+        let merged_len = merged_end - merged_start;
+        if self.len - replaced + merged_len > self.capacity {
+            return false;
+        }
+
+        let mut merged = vec![0; merged_len];
+        merged[start - merged_start..end - merged_start].copy_from_slice(data);
+        for (seq, data) in self.segments.drain(lo..hi) {
+            let offset = rel(seq) - merged_start;
+            merged[offset..offset + data.len()].copy_from_slice(&data);
+        }
+        self.segments
+            .insert(lo, (nxt.wrapping_add(merged_start as u32), merged));
+        self.len = self.len - replaced + merged_len;
+
+        true
+    }
+
+    /// Whether all of `[seq, seq + len)` is already queued.
+    pub(crate) fn contains(&self, seq: u32, len: usize) -> bool {
+        self.segments.iter().any(|(start, data)| {
+            let offset = seq.wrapping_sub(*start) as usize;
+            offset < data.len() && offset + len <= data.len()
+        })
+    }
+
+    /// Ranges of queued data as SACK blocks, in sequence number order.
+    pub(crate) fn blocks(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.segments
+            .iter()
+            .map(|(seq, data)| (*seq, seq.wrapping_add(data.len() as u32)))
+    }
+
+    /// Take the data that continues directly at `nxt`, if the hole before it was filled.
+    pub(crate) fn pop(&mut self, nxt: u32) -> Option<Vec<u8>> {
+        while let Some((seq, _)) = self.segments.front() {
+            let received = nxt.wrapping_sub(*seq);
+            if received >= 1 << 31 {
+                // segment still starts after nxt
+                return None;
+            }
+
+            let (_, mut data) = self.segments.pop_front().unwrap();
+            self.len -= data.len();
+            if (received as usize) < data.len() {
+                data.drain(..received as usize);
+                return Some(data);
+            }
+            // everything in this segment was already received
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reassembles_out_of_order_segments() {
+        let mut a = Assembler::new(1024);
+        assert!(a.insert(100, 110, b"klm"));
+        assert!(a.insert(100, 105, b"fghij"));
+        assert_eq!(a.blocks().collect::<Vec<_>>(), [(105, 113)]);
+        assert!(a.contains(107, 3));
+        assert!(!a.contains(112, 2));
+
+        // The hole at 100 is still open
+        assert_eq!(a.pop(100), None);
+        assert_eq!(a.pop(105).as_deref(), Some(&b"fghijklm"[..]));
+        assert_eq!(a.pop(113), None);
+    }
+
+    #[test]
+    fn merges_overlapping_segments() {
+        let data = b"abcdefghijklmnopqrstuvwxyz";
+        let mut a = Assembler::new(1024);
+        assert!(a.insert(0, 10, &data[..4]));
+        assert!(a.insert(0, 20, &data[10..14]));
+        assert_eq!(a.blocks().count(), 2);
+        assert!(a.insert(0, 12, &data[2..22]));
+        assert_eq!(a.blocks().collect::<Vec<_>>(), [(10, 32)]);
+        assert_eq!(a.pop(10).as_deref(), Some(&data[..22]));
+    }
+
+    #[test]
+    fn drops_data_already_received() {
+        let mut a = Assembler::new(1024);
+        assert!(a.insert(0, 5, b"fghij"));
+        // Everything up to 8 arrived in order meanwhile
+        assert_eq!(a.pop(8).as_deref(), Some(&b"ij"[..]));
+
+        assert!(a.insert(0, 5, b"fgh"));
+        assert_eq!(a.pop(8), None);
+    }
+
+    #[test]
+    fn respects_the_budget() {
+        let mut a = Assembler::new(8);
+        assert!(a.insert(0, 10, b"abcd"));
+        assert!(!a.insert(0, 20, b"vwxyz"));
+        // Overlapping data does not count twice
+        assert!(a.insert(0, 12, b"cdefgh"));
+        assert!(!a.insert(0, 18, b"s"));
+    }
+
+    #[test]
+    fn handles_sequence_number_wraparound() {
+        let mut a = Assembler::new(1024);
+        let nxt = u32::MAX - 2;
+        assert!(a.insert(nxt, 1, b"cd"));
+        assert!(a.insert(nxt, u32::MAX, b"ab"));
+        assert_eq!(a.blocks().collect::<Vec<_>>(), [(u32::MAX, 3)]);
+        assert_eq!(a.pop(u32::MAX).as_deref(), Some(&b"abcd"[..]));
+    }
+}
@@ -0,0 +1,288 @@
+use std::{sync::Arc, time::Duration};
+
+use super::wrapping_lt;
+
+pub use bbr::Bbr;
+pub use cubic::Cubic;
+
+mod bbr;
+mod cubic;
+
+/// Duplicate ACKs that trigger a fast retransmit
+const DUP_ACK_THRESHOLD: u32 = 3;
+
+/// A congestion control algorithm, driven by the ACK, loss and RTO events of a connection.
+///
+/// Loss detection and NewReno fast recovery (RFC 6582) are handled by the connection itself,
+/// an algorithm only decides the size of the congestion window.
+pub trait CongestionControl: Send {
+    /// Congestion window in bytes.
+    fn window(&self) -> usize;
+
+    /// `acked` bytes of new data were acknowledged outside of loss recovery.
+    ///
+    /// `srtt` is the smoothed round-trip time, if one was measured yet.
+    fn on_ack(&mut self, acked: usize, srtt: Option<Duration>);
+
+    /// Duplicate ACKs signalled a loss with `flight` bytes outstanding.
+    fn on_loss(&mut self, flight: usize);
+
+    /// The retransmission timer expired with `flight` bytes outstanding.
+    fn on_rto(&mut self, flight: usize);
+
+    /// A delivery rate sample was taken from an ACK, during loss recovery as well.
+    fn on_rate_sample(&mut self, _sample: &RateSample) {}
+
+    /// Rate in bytes per second to pace segments at, `None` sends as fast as the window allows.
+    fn pacing_rate(&self) -> Option<f64> {
+        None
+    }
+}
+
+/// Delivery rate sample taken when an ACK arrives.
+#[derive(Debug, Clone, Copy)]
+pub struct RateSample {
+    /// bytes newly acknowledged by the ACK
+    pub acked: usize,
+    /// bytes delivered over `interval`
+    pub delivered: u64,
+    pub interval: Duration,
+    /// round-trip time of the most recently sent segment that was acknowledged
+    pub rtt: Duration,
+    /// total bytes delivered when that segment was sent
+    pub prior_delivered: u64,
+    /// total bytes delivered on the connection so far
+    pub total_delivered: u64,
+    /// whether the sender ran out of data while the sample was taken
+    pub is_app_limited: bool,
+    /// bytes in flight after the ACK
+    pub inflight: usize,
+}
+
+impl RateSample {
+    /// Delivery rate in bytes per second.
+    pub fn delivery_rate(&self) -> Option<f64> {
+        if self.interval.is_zero() {
+            return None;
+        }
+        Some(self.delivered as f64 / self.interval.as_secs_f64())
+    }
+}
+
+/// Creates the congestion control of a new connection from its sender maximum segment size.
+pub(crate) type Factory = Arc<dyn Fn(usize) -> Box<dyn CongestionControl> + Send + Sync>;
+
+pub(crate) fn default_factory() -> Factory {
+    Arc::new(|smss| Box::new(Reno::new(smss)))
+}
+
+/// RFC 5681, 3.1: IW = min(4*SMSS, max(2*SMSS, 4380 bytes))
+fn initial_window(smss: usize) -> usize {
+    std::cmp::min(4 * smss, std::cmp::max(2 * smss, 4380))
+}
+
+/// Reno congestion control as described in RFC 5681.
+pub struct Reno {
+    /// sender maximum segment size
+    smss: usize,
+    /// congestion window in bytes
+    cwnd: usize,
+    /// slow start threshold in bytes
+    ssthresh: usize,
+}
+
+impl Reno {
+    pub fn new(smss: usize) -> Self {
+        Reno {
+            smss,
+            cwnd: initial_window(smss),
+            ssthresh: usize::MAX,
+        }
+    }
+}
+
+impl CongestionControl for Reno {
+    fn window(&self) -> usize {
+        self.cwnd
+    }
+
+    fn on_ack(&mut self, acked: usize, _srtt: Option<Duration>) {
+        if self.cwnd < self.ssthresh {
+            // Slow start
+            self.cwnd += std::cmp::min(acked, self.smss);
+        } else {
+            // Congestion avoidance
+            self.cwnd += std::cmp::max(self.smss * self.smss / self.cwnd, 1);
+        }
+    }
+
+    fn on_loss(&mut self, flight: usize) {
+        self.ssthresh = std::cmp::max(flight / 2, 2 * self.smss);
+        self.cwnd = self.ssthresh;
+    }
+
+    fn on_rto(&mut self, flight: usize) {
+        self.ssthresh = std::cmp::max(flight / 2, 2 * self.smss);
+        // RFC 5681, 3.1: the loss window is one segment
+        self.cwnd = self.smss;
+    }
+}
+
+/// Duplicate ACK counting and NewReno fast recovery as described in RFC 6582.
+pub(crate) struct Recovery {
+    /// consecutive duplicate ACKs
+    dup_acks: u32,
+    /// highest sequence number sent when loss recovery started
+    recover: u32,
+    in_recovery: bool,
+    /// bytes the congestion window is inflated by during fast recovery
+    inflation: usize,
+}
+
+impl Recovery {
+    pub(crate) fn new(iss: u32) -> Self {
+        Recovery {
+            dup_acks: 0,
+            recover: iss,
+            in_recovery: false,
+            inflation: 0,
+        }
+    }
+
+    pub(crate) fn in_recovery(&self) -> bool {
+        self.in_recovery
+    }
+
+    pub(crate) fn inflation(&self) -> usize {
+        self.inflation
+    }
+
+    /// `acked` bytes of new data were acknowledged by `ack`.
+    ///
+    /// Returns true for a partial ACK during fast recovery, in which case the first
+    /// unacknowledged segment has to be retransmitted.
+    pub(crate) fn on_ack(&mut self, ack: u32, acked: usize, smss: usize) -> bool {
+        self.dup_acks = 0;
+
+        if !self.in_recovery {
+            return false;
+        }
+
+        if wrapping_lt(self.recover, ack) {
+            // Full acknowledgment, deflate the window
+            self.in_recovery = false;
+            self.inflation = 0;
+            return false;
+        }
+
+        // Partial acknowledgment
+        self.inflation = self.inflation.saturating_sub(acked);
+        if acked >= smss {
+            self.inflation += smss;
+        }
+        true
+    }
+
+    /// Returns true when fast recovery starts and the segment at SND.UNA has to be
+    /// fast retransmitted.
+    pub(crate) fn on_dup_ack(&mut self, ack: u32, nxt: u32, smss: usize) -> bool {
+        self.dup_acks += 1;
+
+        if self.in_recovery {
+            // Every further duplicate ACK means another segment left the network
+            self.inflation += smss;
+            return false;
+        }
+
+        if self.dup_acks == DUP_ACK_THRESHOLD && wrapping_lt(self.recover, ack) {
+            self.recover = nxt.wrapping_sub(1);
+            self.in_recovery = true;
+            self.inflation = DUP_ACK_THRESHOLD as usize * smss;
+            return true;
+        }
+
+        false
+    }
+
+    /// SACK information shows a loss before the duplicate ACK threshold was reached.
+    ///
+    /// Returns true when fast recovery starts.
+    pub(crate) fn on_sack_loss(&mut self, ack: u32, nxt: u32) -> bool {
+        if self.in_recovery || !wrapping_lt(self.recover, ack) {
+            return false;
+        }
+        self.recover = nxt.wrapping_sub(1);
+        self.in_recovery = true;
+        true
+    }
+
+    pub(crate) fn on_rto(&mut self, nxt: u32) {
+        self.dup_acks = 0;
+        self.recover = nxt.wrapping_sub(1);
+        self.in_recovery = false;
+        self.inflation = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMSS: usize = 1000;
+
+    #[test]
+    fn third_duplicate_ack_starts_fast_recovery() {
+        let mut r = Recovery::new(0);
+        assert!(!r.on_dup_ack(1000, 10_000, SMSS));
+        assert!(!r.on_dup_ack(1000, 10_000, SMSS));
+        assert!(r.on_dup_ack(1000, 10_000, SMSS));
+        assert!(r.in_recovery());
+        assert_eq!(r.inflation(), 3 * SMSS);
+
+        // Further duplicates inflate the window
+        assert!(!r.on_dup_ack(1000, 10_000, SMSS));
+        assert_eq!(r.inflation(), 4 * SMSS);
+    }
+
+    #[test]
+    fn partial_and_full_acks() {
+        let mut r = Recovery::new(0);
+        for _ in 0..3 {
+            r.on_dup_ack(1000, 10_000, SMSS);
+        }
+
+        // Partial ACK, the next hole has to be retransmitted
+        assert!(r.on_ack(3000, 2000, SMSS));
+        assert!(r.in_recovery());
+        assert_eq!(r.inflation(), 2 * SMSS);
+
+        // Everything sent before recovery started is acknowledged
+        assert!(!r.on_ack(10_000, 7000, SMSS));
+        assert!(!r.in_recovery());
+        assert_eq!(r.inflation(), 0);
+    }
+
+    #[test]
+    fn no_second_recovery_for_data_sent_before_a_timeout() {
+        let mut r = Recovery::new(0);
+        r.on_rto(10_000);
+        for _ in 0..3 {
+            assert!(!r.on_dup_ack(5000, 12_000, SMSS));
+        }
+        assert!(!r.on_sack_loss(5000, 12_000));
+
+        assert!(r.on_sack_loss(10_000, 12_000));
+        assert!(r.in_recovery());
+    }
+
+    #[test]
+    fn timeout_ends_recovery() {
+        let mut r = Recovery::new(0);
+        for _ in 0..3 {
+            r.on_dup_ack(1000, 10_000, SMSS);
+        }
+        r.on_rto(10_000);
+        assert!(!r.in_recovery());
+        assert_eq!(r.inflation(), 0);
+    }
+}
@@ -0,0 +1,300 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use super::{CongestionControl, RateSample, initial_window};
+
+/// 2/ln(2), the smallest gain that doubles the sending rate every round in startup
+const HIGH_GAIN: f64 = 2.885;
+/// Pacing gains cycled through in PROBE_BW, one phase per min RTT
+const PACING_GAIN_CYCLE: [f64; 8] = [1.25, 0.75, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0];
+const CWND_GAIN: f64 = 2.0;
+/// Rounds the bottleneck bandwidth filter remembers samples for
+const BTL_BW_FILTER_ROUNDS: u64 = 10;
+/// Age after which the min RTT estimate is refreshed through PROBE_RTT
+const MIN_RTT_FILTER: Duration = Duration::from_secs(10);
+const PROBE_RTT_DURATION: Duration = Duration::from_millis(200);
+/// Smallest congestion window in segments
+const MIN_PIPE_CWND: usize = 4;
+/// Growth below which the pipe is considered full
+const FULL_BW_THRESHOLD: f64 = 1.25;
+const FULL_BW_ROUNDS: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Startup,
+    Drain,
+    ProbeBw,
+    ProbeRtt,
+}
+
+/// Windowed maximum of the delivery rate over the last few rounds
+struct MaxFilter {
+    /// (round, bytes per second), decreasing in rate
+    samples: VecDeque<(u64, f64)>,
+}
+
+impl MaxFilter {
+    fn get(&self) -> f64 {
+        self.samples.front().map_or(0.0, |&(_, bw)| bw)
+    }
+
+    fn update(&mut self, round: u64, bw: f64) {
+        while let Some(&(_, last)) = self.samples.back()
+            && last <= bw
+        {
+            self.samples.pop_back();
+        }
+        self.samples.push_back((round, bw));
+        while let Some(&(at, _)) = self.samples.front()
+            && at + BTL_BW_FILTER_ROUNDS <= round
+        {
+            self.samples.pop_front();
+        }
+    }
+}
+
+/// BBR (v1) congestion control, driven by delivery rate samples rather than losses.
+pub struct Bbr {
+    /// sender maximum segment size
+    smss: usize,
+    /// congestion window in bytes
+    cwnd: usize,
+    /// congestion window saved before an RTO or PROBE_RTT
+    prior_cwnd: usize,
+    mode: Mode,
+    pacing_gain: f64,
+    cwnd_gain: f64,
+
+    btl_bw: MaxFilter,
+    min_rtt: Option<Duration>,
+    min_rtt_stamp: Instant,
+
+    round_count: u64,
+    next_round_delivered: u64,
+    round_start: bool,
+
+    full_bw: f64,
+    full_bw_count: u32,
+    filled_pipe: bool,
+
+    cycle_index: usize,
+    cycle_stamp: Instant,
+
+    probe_rtt_done_stamp: Option<Instant>,
+    probe_rtt_round_done: bool,
+}
+
+impl Bbr {
+    pub fn new(smss: usize) -> Self {
+        let now = Instant::now();
+        Bbr {
+            smss,
+            cwnd: initial_window(smss),
+            prior_cwnd: 0,
+            mode: Mode::Startup,
+            pacing_gain: HIGH_GAIN,
+            cwnd_gain: HIGH_GAIN,
+            btl_bw: MaxFilter {
+                samples: VecDeque::new(),
+            },
+            min_rtt: None,
+            min_rtt_stamp: now,
+            round_count: 0,
+            next_round_delivered: 0,
+            round_start: false,
+            full_bw: 0.0,
+            full_bw_count: 0,
+            filled_pipe: false,
+            cycle_index: 0,
+            cycle_stamp: now,
+            probe_rtt_done_stamp: None,
+            probe_rtt_round_done: false,
+        }
+    }
+
+    /// Bandwidth-delay product scaled by `gain`, in bytes
+    fn bdp(&self, gain: f64) -> usize {
+        match self.min_rtt {
+            Some(min_rtt) if self.btl_bw.get() > 0.0 => {
+                (self.btl_bw.get() * min_rtt.as_secs_f64() * gain) as usize
+            }
+            _ => initial_window(self.smss),
+        }
+    }
+
+    fn update_round(&mut self, rs: &RateSample) {
+        self.round_start = rs.prior_delivered >= self.next_round_delivered;
+        if self.round_start {
+            self.next_round_delivered = rs.total_delivered;
+            self.round_count += 1;
+        }
+    }
+
+    fn update_btl_bw(&mut self, rs: &RateSample) {
+        if let Some(rate) = rs.delivery_rate()
+            && (rate >= self.btl_bw.get() || !rs.is_app_limited)
+        {
+            self.btl_bw.update(self.round_count, rate);
+        }
+    }
+
+    fn enter_probe_bw(&mut self, now: Instant) {
+        self.mode = Mode::ProbeBw;
+        self.cwnd_gain = CWND_GAIN;
+        // Start anywhere but in the draining phase
+        self.cycle_index =
+            (rand::random_range(0..PACING_GAIN_CYCLE.len() - 1) + 2) % PACING_GAIN_CYCLE.len();
+        self.pacing_gain = PACING_GAIN_CYCLE[self.cycle_index];
+        self.cycle_stamp = now;
+    }
+
+    fn update_cycle_phase(&mut self, rs: &RateSample, now: Instant) {
+        if self.mode != Mode::ProbeBw {
+            return;
+        }
+
+        let full_length = now - self.cycle_stamp > self.min_rtt.unwrap_or_default();
+        let next = if self.pacing_gain > 1.0 {
+            full_length && rs.inflight >= self.bdp(self.pacing_gain)
+        } else if self.pacing_gain < 1.0 {
+            full_length || rs.inflight <= self.bdp(1.0)
+        } else {
+            full_length
+        };
+
+        if next {
+            self.cycle_index = (self.cycle_index + 1) % PACING_GAIN_CYCLE.len();
+            self.pacing_gain = PACING_GAIN_CYCLE[self.cycle_index];
+            self.cycle_stamp = now;
+        }
+    }
+
+    fn check_full_pipe(&mut self, rs: &RateSample) {
+        if self.filled_pipe || !self.round_start || rs.is_app_limited {
+            return;
+        }
+
+        if self.btl_bw.get() >= self.full_bw * FULL_BW_THRESHOLD {
+            self.full_bw = self.btl_bw.get();
+            self.full_bw_count = 0;
+            return;
+        }
+
+        self.full_bw_count += 1;
+        self.filled_pipe = self.full_bw_count >= FULL_BW_ROUNDS;
+    }
+
+    fn check_drain(&mut self, rs: &RateSample, now: Instant) {
+        if self.mode == Mode::Startup && self.filled_pipe {
+            self.mode = Mode::Drain;
+            self.pacing_gain = 1.0 / HIGH_GAIN;
+            self.cwnd_gain = HIGH_GAIN;
+        }
+        if self.mode == Mode::Drain && rs.inflight <= self.bdp(1.0) {
+            self.enter_probe_bw(now);
+        }
+    }
+
+    fn update_min_rtt(&mut self, rs: &RateSample, now: Instant) {
+        let expired = now > self.min_rtt_stamp + MIN_RTT_FILTER;
+        if self.min_rtt.is_none_or(|min_rtt| rs.rtt <= min_rtt) || expired {
+            self.min_rtt = Some(rs.rtt);
+            self.min_rtt_stamp = now;
+        }
+
+        if expired && self.mode != Mode::ProbeRtt {
+            self.mode = Mode::ProbeRtt;
+            self.pacing_gain = 1.0;
+            self.cwnd_gain = 1.0;
+            self.prior_cwnd = self.cwnd;
+            self.probe_rtt_done_stamp = None;
+        }
+
+        if self.mode == Mode::ProbeRtt {
+            self.handle_probe_rtt(rs, now);
+        }
+    }
+
+    fn handle_probe_rtt(&mut self, rs: &RateSample, now: Instant) {
+        match self.probe_rtt_done_stamp {
+            None if rs.inflight <= MIN_PIPE_CWND * self.smss => {
+                self.probe_rtt_done_stamp = Some(now + PROBE_RTT_DURATION);
+                self.probe_rtt_round_done = false;
+                self.next_round_delivered = rs.total_delivered;
+            }
+            None => {}
+            Some(done) => {
+                if self.round_start {
+                    self.probe_rtt_round_done = true;
+                }
+                if self.probe_rtt_round_done && now > done {
+                    self.min_rtt_stamp = now;
+                    self.cwnd = std::cmp::max(self.cwnd, self.prior_cwnd);
+                    if self.filled_pipe {
+                        self.enter_probe_bw(now);
+                    } else {
+                        self.mode = Mode::Startup;
+                        self.pacing_gain = HIGH_GAIN;
+                        self.cwnd_gain = HIGH_GAIN;
+                    }
+                }
+            }
+        }
+    }
+
+    fn update_cwnd(&mut self, rs: &RateSample) {
+        // Leave room for delayed and aggregated ACKs
+        let target = self.bdp(self.cwnd_gain) + 3 * self.smss;
+        if self.filled_pipe {
+            self.cwnd = std::cmp::min(self.cwnd + rs.acked, target);
+        } else if self.cwnd < target || rs.total_delivered < initial_window(self.smss) as u64 {
+            self.cwnd += rs.acked;
+        }
+        self.cwnd = std::cmp::max(self.cwnd, MIN_PIPE_CWND * self.smss);
+
+        if self.mode == Mode::ProbeRtt {
+            self.cwnd = std::cmp::min(self.cwnd, MIN_PIPE_CWND * self.smss);
+        }
+    }
+}
+
+impl CongestionControl for Bbr {
+    fn window(&self) -> usize {
+        self.cwnd
+    }
+
+    fn on_ack(&mut self, _acked: usize, _srtt: Option<Duration>) {
+        // The window is driven by rate samples instead
+    }
+
+    fn on_loss(&mut self, _flight: usize) {
+        // BBR does not treat loss as a congestion signal
+    }
+
+    fn on_rto(&mut self, _flight: usize) {
+        self.prior_cwnd = self.cwnd;
+        self.cwnd = self.smss;
+    }
+
+    fn on_rate_sample(&mut self, rs: &RateSample) {
+        let now = Instant::now();
+        self.update_round(rs);
+        self.update_btl_bw(rs);
+        self.update_cycle_phase(rs, now);
+        self.check_full_pipe(rs);
+        self.check_drain(rs, now);
+        self.update_min_rtt(rs, now);
+        self.update_cwnd(rs);
+    }
+
+    fn pacing_rate(&self) -> Option<f64> {
+        let btl_bw = self.btl_bw.get();
+        if btl_bw > 0.0 {
+            Some(self.pacing_gain * btl_bw)
+        } else {
+            None
+        }
+    }
+}
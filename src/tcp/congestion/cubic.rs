@@ -0,0 +1,118 @@
+use std::time::{Duration, Instant};
+
+use super::{CongestionControl, initial_window};
+
+/// CUBIC scaling constant
+const C: f64 = 0.4;
+/// Multiplicative decrease factor
+const BETA: f64 = 0.7;
+
+/// CUBIC congestion control as described in RFC 9438.
+pub struct Cubic {
+    /// sender maximum segment size
+    smss: usize,
+    /// congestion window in bytes
+    cwnd: usize,
+    /// slow start threshold in bytes
+    ssthresh: usize,
+    /// window in segments just before the last reduction
+    w_max: f64,
+    /// W_max before the previous reduction, for fast convergence
+    w_last_max: f64,
+    /// Reno-friendly window estimate in segments
+    w_est: f64,
+    /// time to reach W_max again after a reduction
+    k: f64,
+    /// start of the current congestion avoidance epoch
+    epoch_start: Option<Instant>,
+}
+
+impl Cubic {
+    pub fn new(smss: usize) -> Self {
+        Cubic {
+            smss,
+            cwnd: initial_window(smss),
+            ssthresh: usize::MAX,
+            w_max: 0.0,
+            w_last_max: 0.0,
+            w_est: 0.0,
+            k: 0.0,
+            epoch_start: None,
+        }
+    }
+
+    fn segments(&self) -> f64 {
+        self.cwnd as f64 / self.smss as f64
+    }
+
+    /// W_cubic(t) = C*(t-K)^3 + W_max
+    fn w_cubic(&self, t: f64) -> f64 {
+        C * (t - self.k).powi(3) + self.w_max
+    }
+
+    fn reduce(&mut self) {
+        let cwnd = self.segments();
+        // Fast convergence: release bandwidth faster when the window keeps shrinking
+        self.w_max = if cwnd < self.w_last_max {
+            cwnd * (1.0 + BETA) / 2.0
+        } else {
+            cwnd
+        };
+        self.w_last_max = cwnd;
+        self.ssthresh = std::cmp::max((cwnd * BETA * self.smss as f64) as usize, 2 * self.smss);
+        self.epoch_start = None;
+    }
+}
+
+impl CongestionControl for Cubic {
+    fn window(&self) -> usize {
+        self.cwnd
+    }
+
+    fn on_ack(&mut self, acked: usize, srtt: Option<Duration>) {
+        if self.cwnd < self.ssthresh {
+            // Slow start
+            self.cwnd += std::cmp::min(acked, self.smss);
+            return;
+        }
+
+        let now = Instant::now();
+        let cwnd = self.segments();
+        let epoch_start = *self.epoch_start.get_or_insert_with(|| {
+            if self.w_max < cwnd {
+                // No reduction happened yet, grow from the current window
+                self.w_max = cwnd;
+            }
+            self.k = ((self.w_max - cwnd) / C).cbrt();
+            self.w_est = cwnd;
+            now
+        });
+
+        let t = (now - epoch_start).as_secs_f64();
+        let rtt = srtt.unwrap_or(Duration::ZERO).as_secs_f64();
+        let acked_segments = acked as f64 / self.smss as f64;
+
+        // Reno-friendly region
+        let alpha = 3.0 * (1.0 - BETA) / (1.0 + BETA);
+        self.w_est += alpha * acked_segments / cwnd;
+
+        let target = self.w_cubic(t + rtt).clamp(cwnd, 1.5 * cwnd);
+        let next = if self.w_est > target {
+            self.w_est
+        } else {
+            cwnd + (target - cwnd) * acked_segments / cwnd
+        };
+
+        self.cwnd = std::cmp::max((next * self.smss as f64) as usize, self.cwnd);
+    }
+
+    fn on_loss(&mut self, _flight: usize) {
+        self.reduce();
+        self.cwnd = self.ssthresh;
+    }
+
+    fn on_rto(&mut self, _flight: usize) {
+        self.reduce();
+        self.cwnd = self.smss;
+    }
+}
@@ -0,0 +1,210 @@
+use std::{
+    hash::{BuildHasher, RandomState},
+    net::SocketAddr,
+    time::Instant,
+};
+
+/// MSS values a cookie can encode, the largest not above the peer's MSS is used
+const MSS_TABLE: [u16; 4] = [536, 1220, 1440, 1460];
+/// Seconds per counter value, a cookie is valid during its period and the next one
+const PERIOD: u64 = 64;
+/// Window scale code of a SYN without the option
+const NO_WSCALE: u32 = 15;
+
+/// Options of a SYN carried in its cookie
+pub(crate) struct CookieOptions {
+    pub(crate) mss: u16,
+    pub(crate) wscale: Option<u8>,
+    pub(crate) sack_permitted: bool,
+}
+
+/// Secret for SYN cookies (RFC 4987, 3.6).
+///
+/// A cookie is laid out as a 5 bit counter, a 20 bit keyed hash and 7 bits of options:
+/// the MSS index, the window scale and whether SACK is permitted. The hash covers the
+/// addresses, the peer's ISN, the counter and the options, so none can be forged.
+pub(crate) struct SynCookies {
+    key: RandomState,
+    epoch: Instant,
+    /// counter of the last cookie sent
+    sent: Option<u32>,
+}
+
+impl SynCookies {
+    pub(crate) fn new() -> Self {
+        SynCookies {
+            key: RandomState::new(),
+            epoch: Instant::now(),
+            sent: None,
+        }
+    }
+
+    /// Whether a cookie sent recently may still come back, only then ACKs are checked
+    /// for one so the hash cannot be guessed at leisure.
+    pub(crate) fn recently_sent(&self) -> bool {
+        self.sent
+            .is_some_and(|sent| self.counter().wrapping_sub(sent) <= 1)
+    }
+
+    fn counter(&self) -> u32 {
+        (self.epoch.elapsed().as_secs() / PERIOD) as u32
+    }
+
+    fn hash(
+        &self,
+        remote: SocketAddr,
+        local: SocketAddr,
+        irs: u32,
+        counter: u32,
+        data: u32,
+    ) -> u32 {
+        self.key
+            .hash_one((remote, local, irs, counter & 0x1f, data)) as u32
+            & 0xfffff
+    }
+
+    /// The ISS for a SYN with sequence number `irs`, and the MSS it encodes.
+    pub(crate) fn encode(
+        &mut self,
+        remote: SocketAddr,
+        local: SocketAddr,
+        irs: u32,
+        options: &CookieOptions,
+    ) -> (u32, u16) {
+        let mss_index = MSS_TABLE
+            .iter()
+            .rposition(|&mss| mss <= options.mss)
+            .unwrap_or(0);
+        let wscale = options.wscale.map_or(NO_WSCALE, u32::from);
+        let data = (mss_index as u32) << 5 | wscale << 1 | options.sack_permitted as u32;
+
+        let counter = self.counter();
+        self.sent = Some(counter);
+        let cookie =
+            (counter & 0x1f) << 27 | self.hash(remote, local, irs, counter, data) << 7 | data;
+        (cookie, MSS_TABLE[mss_index])
+    }
+
+    /// The options of a SYN with sequence number `irs`, if `cookie` is valid for it.
+    pub(crate) fn decode(
+        &self,
+        remote: SocketAddr,
+        local: SocketAddr,
+        irs: u32,
+        cookie: u32,
+    ) -> Option<CookieOptions> {
+        let now = self.counter();
+        let age = now.wrapping_sub(cookie >> 27) & 0x1f;
+        if age > 1 {
+            return None;
+        }
+
+        let data = cookie & 0x7f;
+        if (cookie >> 7) & 0xfffff != self.hash(remote, local, irs, now.wrapping_sub(age), data) {
+            return None;
+        }
+
+        let wscale = (data >> 1) & 0xf;
+        Some(CookieOptions {
+            mss: MSS_TABLE[(data >> 5) as usize],
+            wscale: (wscale != NO_WSCALE).then_some(wscale as u8),
+            sack_permitted: data & 1 == 1,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addrs() -> (SocketAddr, SocketAddr) {
+        (
+            "10.0.0.2:40000".parse().unwrap(),
+            "10.0.0.1:80".parse().unwrap(),
+        )
+    }
+
+    #[test]
+    fn round_trips_the_options() {
+        let mut cookies = SynCookies::new();
+        let (remote, local) = addrs();
+        let options = CookieOptions {
+            mss: 1400,
+            wscale: Some(7),
+            sack_permitted: true,
+        };
+        let (cookie, mss) = cookies.encode(remote, local, 1000, &options);
+        // Rounded down to the table
+        assert_eq!(mss, 1220);
+
+        let decoded = cookies.decode(remote, local, 1000, cookie).unwrap();
+        assert_eq!(decoded.mss, 1220);
+        assert_eq!(decoded.wscale, Some(7));
+        assert!(decoded.sack_permitted);
+    }
+
+    #[test]
+    fn encodes_missing_options() {
+        let mut cookies = SynCookies::new();
+        let (remote, local) = addrs();
+        let options = CookieOptions {
+            mss: 100,
+            wscale: None,
+            sack_permitted: false,
+        };
+        let (cookie, mss) = cookies.encode(remote, local, 7, &options);
+        assert_eq!(mss, 536);
+
+        let decoded = cookies.decode(remote, local, 7, cookie).unwrap();
+        assert_eq!(decoded.wscale, None);
+        assert!(!decoded.sack_permitted);
+    }
+
+    #[test]
+    fn remembers_recent_cookies() {
+        let mut cookies = SynCookies::new();
+        assert!(!cookies.recently_sent());
+
+        let (remote, local) = addrs();
+        let options = CookieOptions {
+            mss: 1460,
+            wscale: None,
+            sack_permitted: false,
+        };
+        cookies.encode(remote, local, 1000, &options);
+        assert!(cookies.recently_sent());
+
+        // Two periods later no cookie can still be valid
+        cookies.sent = Some(cookies.counter().wrapping_sub(2));
+        assert!(!cookies.recently_sent());
+    }
+
+    #[test]
+    fn rejects_forged_cookies() {
+        let mut cookies = SynCookies::new();
+        let (remote, local) = addrs();
+        let options = CookieOptions {
+            mss: 1460,
+            wscale: Some(2),
+            sack_permitted: false,
+        };
+        let (cookie, _) = cookies.encode(remote, local, 1000, &options);
+
+        // Another ISN, another peer, or altered options
+        assert!(cookies.decode(remote, local, 1001, cookie).is_none());
+        assert!(cookies.decode(local, remote, 1000, cookie).is_none());
+        assert!(cookies.decode(remote, local, 1000, cookie ^ 1).is_none());
+        // From a counter that is long gone
+        assert!(
+            cookies
+                .decode(remote, local, 1000, cookie ^ 4 << 27)
+                .is_none()
+        );
+        // Another secret
+        assert!(
+            SynCookies::new()
+                .decode(remote, local, 1000, cookie)
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use std::{collections::VecDeque, time::Instant};
+
+use super::{congestion::RateSample, wrapping_lt};
+
+/// State of the connection when a segment was first transmitted
+struct Packet {
+    /// sequence number following the segment
+    end: u32,
+    sent_time: Instant,
+    delivered: u64,
+    delivered_time: Instant,
+    first_sent_time: Instant,
+    is_app_limited: bool,
+}
+
+/// Delivery rate estimation, following draft-cheng-iccrg-delivery-rate-estimation.
+pub(crate) struct RateSampler {
+    /// bytes delivered over the lifetime of the connection
+    delivered: u64,
+    delivered_time: Instant,
+    first_sent_time: Instant,
+    /// `delivered` at the end of an application limited period, zero if there is none
+    app_limited: u64,
+    packets: VecDeque<Packet>,
+}
+
+impl RateSampler {
+    pub(crate) fn new() -> Self {
+        let now = Instant::now();
+        RateSampler {
+            delivered: 0,
+            delivered_time: now,
+            first_sent_time: now,
+            app_limited: 0,
+            packets: VecDeque::new(),
+        }
+    }
+
+    /// A segment ending before `end` was transmitted for the first time.
+    pub(crate) fn on_send(&mut self, end: u32, inflight: usize, now: Instant) {
+        if inflight == 0 {
+            self.first_sent_time = now;
+            self.delivered_time = now;
+        }
+
+        self.packets.push_back(Packet {
+            end,
+            sent_time: now,
+            delivered: self.delivered,
+            delivered_time: self.delivered_time,
+            first_sent_time: self.first_sent_time,
+            is_app_limited: self.app_limited != 0,
+        });
+    }
+
+    /// The sender ran out of data to send with `inflight` bytes outstanding.
+    pub(crate) fn on_app_limited(&mut self, inflight: usize) {
+        self.app_limited = std::cmp::max(self.delivered + inflight as u64, 1);
+    }
+
+    /// `acked` bytes were newly acknowledged by `ack`, leaving `inflight` bytes outstanding.
+    pub(crate) fn on_ack(&mut self, ack: u32, acked: usize, inflight: usize) -> Option<RateSample> {
+        let now = Instant::now();
+        self.delivered += acked as u64;
+        self.delivered_time = now;
+
+        let mut newest = None;
+        while let Some(packet) = self.packets.front()
+            && !wrapping_lt(ack, packet.end)
+        {
+            newest = self.packets.pop_front();
+        }
+
+        if self.app_limited != 0 && self.delivered > self.app_limited {
+            self.app_limited = 0;
+        }
+
+        let packet = newest?;
+        self.first_sent_time = packet.sent_time;
+
+        let send_elapsed = packet.sent_time - packet.first_sent_time;
+        let ack_elapsed = self.delivered_time - packet.delivered_time;
+
+        Some(RateSample {
+            acked,
+            delivered: self.delivered - packet.delivered,
+            interval: std::cmp::max(send_elapsed, ack_elapsed),
+            rtt: now - packet.sent_time,
+            prior_delivered: packet.delivered,
+            total_delivered: self.delivered,
+            is_app_limited: packet.is_app_limited,
+            inflight,
+        })
+    }
+
+    pub(crate) fn on_rto(&mut self) {
+        // Everything outstanding is considered lost and will be sent again
+        self.packets.clear();
+    }
+}
@@ -0,0 +1,313 @@
+use super::wrapping_lt;
+
+/// Duplicate ACK threshold used to declare a hole lost
+const DUP_THRESH: usize = 3;
+
+/// Sender side SACK scoreboard and loss recovery as described in RFC 6675.
+pub(crate) struct Scoreboard {
+    /// SACKed ranges above SND.UNA, sorted, never overlapping or touching
+    sacked: Vec<(u32, u32)>,
+    /// highest sequence number retransmitted during the current recovery
+    high_rxt: u32,
+    /// everything not SACKed below this was outstanding at a timeout and is lost
+    lost_until: u32,
+}
+
+/// A range of unacknowledged data that was not SACKed
+struct Hole {
+    start: u32,
+    end: u32,
+    lost: bool,
+}
+
+impl Scoreboard {
+    pub(crate) fn new(iss: u32) -> Self {
+        Scoreboard {
+            sacked: Vec::new(),
+            high_rxt: iss,
+            lost_until: iss,
+        }
+    }
+
+    /// Record SACK blocks from an ACK. Blocks outside of (SND.UNA, SND.NXT] are D-SACKs
+    /// or bogus, and only their overlap is kept.
+    pub(crate) fn update(&mut self, una: u32, nxt: u32, blocks: &[(u32, u32)]) {
+        let rel = |seq: u32| seq.wrapping_sub(una);
+        let limit = rel(nxt);
+
+        for &(start, end) in blocks {
+            if !wrapping_lt(start, end) {
+                continue;
+            }
+            let start = if wrapping_lt(start, una) {
+                0
+            } else {
+                rel(start)
+            };
+            let end = if wrapping_lt(end, una) { 0 } else { rel(end) };
+            let end = std::cmp::min(end, limit);
+            if start >= end || start >= limit {
+                continue;
+            }
+            self.insert(una, start, end);
+        }
+    }
+
+    fn insert(&mut self, una: u32, start: u32, end: u32) {
+        let rel = |seq: u32| seq.wrapping_sub(una);
+        let lo = self.sacked.partition_point(|&(_, e)| rel(e) < start);
+        let hi = self.sacked.partition_point(|&(s, _)| rel(s) <= end);
+
+        let mut merged = (start, end);
+        for &(s, e) in &self.sacked[lo..hi] {
+            merged.0 = std::cmp::min(merged.0, rel(s));
+            merged.1 = std::cmp::max(merged.1, rel(e));
+        }
+        self.sacked.splice(
+            lo..hi,
+            [(una.wrapping_add(merged.0), una.wrapping_add(merged.1))],
+        );
+    }
+
+    /// SND.UNA advanced to `una`.
+    pub(crate) fn on_ack(&mut self, una: u32) {
+        if wrapping_lt(self.lost_until, una) {
+            self.lost_until = una;
+        }
+        self.sacked.retain_mut(|(start, end)| {
+            if !wrapping_lt(una, *end) {
+                return false;
+            }
+            if wrapping_lt(*start, una) {
+                *start = una;
+            }
+            true
+        });
+    }
+
+    /// Loss recovery starts at SND.UNA.
+    pub(crate) fn on_recovery(&mut self, una: u32) {
+        self.high_rxt = una;
+    }
+
+    /// `end` is the sequence number following a retransmitted segment.
+    pub(crate) fn on_retransmit(&mut self, end: u32) {
+        if wrapping_lt(self.high_rxt, end) {
+            self.high_rxt = end;
+        }
+    }
+
+    /// The retransmission timer expired, everything outstanding that was not SACKed is
+    /// lost and retransmitted starting at SND.UNA (RFC 6675, 5.1).
+    pub(crate) fn on_rto(&mut self, una: u32, nxt: u32) {
+        // SND.UNA stopped right at SACKed data, the peer reneged on it (RFC 2018, 8)
+        if self.sacked.first().is_some_and(|&(start, _)| start == una) {
+            self.sacked.clear();
+        }
+        self.high_rxt = una;
+        self.lost_until = nxt;
+    }
+
+    /// Whether data outstanding at the last timeout is still unacknowledged, until then
+    /// the scoreboard drives the retransmissions.
+    pub(crate) fn in_loss(&self, una: u32) -> bool {
+        wrapping_lt(una, self.lost_until)
+    }
+
+    /// IsLost(SND.UNA) from RFC 6675
+    pub(crate) fn is_lost(&self, una: u32, nxt: u32, smss: usize) -> bool {
+        self.holes(una, nxt, smss)
+            .next()
+            .is_some_and(|hole| hole.lost)
+    }
+
+    fn holes(&self, una: u32, nxt: u32, smss: usize) -> impl Iterator<Item = Hole> + '_ {
+        let lost_until = self.lost_until;
+        self.sacked_holes(una, nxt, smss).flat_map(move |hole| {
+            // Data outstanding at the last timeout is lost as well
+            let (lost, rest) = if hole.lost || !wrapping_lt(hole.start, lost_until) {
+                (hole, None)
+            } else if wrapping_lt(lost_until, hole.end) {
+                let rest = Hole {
+                    start: lost_until,
+                    end: hole.end,
+                    lost: false,
+                };
+                let lost = Hole {
+                    start: hole.start,
+                    end: lost_until,
+                    lost: true,
+                };
+                (lost, Some(rest))
+            } else {
+                (Hole { lost: true, ..hole }, None)
+            };
+            std::iter::once(lost).chain(rest)
+        })
+    }
+
+    /// The ranges between SACKed data, lost once enough was SACKed above them
+    fn sacked_holes(&self, una: u32, nxt: u32, smss: usize) -> impl Iterator<Item = Hole> + '_ {
+        let mut start = una;
+        let mut blocks = self.sacked.iter().enumerate();
+        std::iter::from_fn(move || {
+            loop {
+                match blocks.next() {
+                    Some((i, &(s, e))) => {
+                        let hole = (start, s);
+                        start = e;
+                        if hole.0 == hole.1 {
+                            continue;
+                        }
+                        // Everything SACKed above the hole counts against it
+                        let above = &self.sacked[i..];
+                        let sacked: usize =
+                            above.iter().map(|&(s, e)| e.wrapping_sub(s) as usize).sum();
+                        let lost = above.len() >= DUP_THRESH || sacked > (DUP_THRESH - 1) * smss;
+                        return Some(Hole {
+                            start: hole.0,
+                            end: hole.1,
+                            lost,
+                        });
+                    }
+                    None if wrapping_lt(start, nxt) => {
+                        // Nothing was SACKed above the last hole
+                        let hole = Hole {
+                            start,
+                            end: nxt,
+                            lost: false,
+                        };
+                        start = nxt;
+                        return Some(hole);
+                    }
+                    None => return None,
+                }
+            }
+        })
+    }
+
+    /// The "pipe" of RFC 6675: bytes believed to still be in the network.
+    pub(crate) fn pipe(&self, una: u32, nxt: u32, smss: usize) -> usize {
+        self.holes(una, nxt, smss)
+            .map(|hole| {
+                let len = hole.end.wrapping_sub(hole.start) as usize;
+                let mut pipe = if hole.lost { 0 } else { len };
+                if wrapping_lt(hole.start, self.high_rxt) {
+                    // Retransmissions are in flight as well
+                    let end = if wrapping_lt(self.high_rxt, hole.end) {
+                        self.high_rxt
+                    } else {
+                        hole.end
+                    };
+                    pipe += end.wrapping_sub(hole.start) as usize;
+                }
+                pipe
+            })
+            .sum()
+    }
+
+    /// NextSeg() from RFC 6675: the first lost range not retransmitted yet.
+    pub(crate) fn next_seg(&self, una: u32, nxt: u32, smss: usize) -> Option<(u32, usize)> {
+        self.holes(una, nxt, smss)
+            .filter(|hole| hole.lost && wrapping_lt(self.high_rxt, hole.end))
+            .map(|hole| {
+                let start = if wrapping_lt(hole.start, self.high_rxt) {
+                    self.high_rxt
+                } else {
+                    hole.start
+                };
+                (start, hole.end.wrapping_sub(start) as usize)
+            })
+            .next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMSS: usize = 100;
+
+    #[test]
+    fn merges_and_trims_sacked_ranges() {
+        let mut s = Scoreboard::new(0);
+        s.update(0, 1000, &[(200, 300), (400, 500)]);
+        s.update(0, 1000, &[(300, 400), (900, 1200)]);
+        assert_eq!(s.sacked, [(200, 500), (900, 1000)]);
+
+        s.on_ack(250);
+        assert_eq!(s.sacked, [(250, 500), (900, 1000)]);
+        s.on_ack(500);
+        assert_eq!(s.sacked, [(900, 1000)]);
+    }
+
+    #[test]
+    fn ignores_blocks_below_una() {
+        let mut s = Scoreboard::new(0);
+        // A D-SACK for data that was already acknowledged
+        s.update(500, 1000, &[(100, 200), (u32::MAX - 10, 10)]);
+        assert!(s.sacked.is_empty());
+    }
+
+    #[test]
+    fn a_hole_is_lost_after_enough_sacks() {
+        let mut s = Scoreboard::new(0);
+        s.update(0, 500, &[(100, 200)]);
+        assert!(!s.is_lost(0, 500, SMSS));
+        assert_eq!(s.next_seg(0, 500, SMSS), None);
+
+        s.update(0, 500, &[(250, 300)]);
+        assert!(!s.is_lost(0, 500, SMSS));
+        // A third block above the hole
+        s.update(0, 500, &[(350, 360)]);
+        assert!(s.is_lost(0, 500, SMSS));
+        assert_eq!(s.next_seg(0, 500, SMSS), Some((0, 100)));
+    }
+
+    #[test]
+    fn pipe_counts_unsacked_and_retransmitted_data() {
+        let mut s = Scoreboard::new(0);
+        assert_eq!(s.pipe(0, 1000, SMSS), 1000);
+
+        s.update(0, 1000, &[(100, 400)]);
+        // The hole before the SACKed range is lost, only the data after it counts
+        assert_eq!(s.pipe(0, 1000, SMSS), 600);
+        assert_eq!(s.next_seg(0, 1000, SMSS), Some((0, 100)));
+
+        s.on_recovery(0);
+        s.on_retransmit(100);
+        assert_eq!(s.pipe(0, 1000, SMSS), 700);
+        assert_eq!(s.next_seg(0, 1000, SMSS), None);
+    }
+
+    #[test]
+    fn timeout_marks_everything_outstanding_lost() {
+        let mut s = Scoreboard::new(0);
+        s.update(0, 1000, &[(100, 400)]);
+        s.on_rto(0, 1000);
+        assert!(s.in_loss(0));
+        // Only the data retransmitted since counts, SACKed data is skipped
+        assert_eq!(s.pipe(0, 1000, SMSS), 0);
+        assert_eq!(s.next_seg(0, 1000, SMSS), Some((0, 100)));
+        s.on_retransmit(100);
+        assert_eq!(s.next_seg(0, 1000, SMSS), Some((400, 600)));
+
+        // Data sent after the timeout is not lost
+        assert_eq!(s.pipe(0, 1200, SMSS), 100 + 200);
+        assert_eq!(s.next_seg(0, 1200, SMSS), Some((400, 600)));
+
+        s.on_ack(1000);
+        assert!(!s.in_loss(1000));
+        assert_eq!(s.next_seg(1000, 1200, SMSS), None);
+    }
+
+    #[test]
+    fn timeout_forgets_reneged_sacks() {
+        let mut s = Scoreboard::new(0);
+        s.update(0, 1000, &[(100, 400)]);
+        // The peer acknowledged up to the SACKed range but not into it
+        s.on_ack(100);
+        s.on_rto(100, 1000);
+        assert_eq!(s.next_seg(100, 1000, SMSS), Some((100, 900)));
+    }
+}
@@ -0,0 +1,303 @@
+use std::{
+    collections::VecDeque,
+    io,
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use etherparse::{IpSlice, PacketBuilder, UdpSlice};
+
+use crate::{
+    ConnectionManager, InterfaceHandle, TTL, check_timeout, deadline, device::Device,
+    find_ephemeral_port, local_ip, shut_down_error, tcp::to_ipv6, wait_until,
+};
+
+/// A bound UDP port, the state shared by a `UdpSocket` and the packet loop
+pub(crate) struct Socket {
+    /// only datagrams from this address are received once connected
+    peer: Option<SocketAddr>,
+    /// received datagrams and their sender
+    incomming: VecDeque<(SocketAddr, Vec<u8>)>,
+    /// payload bytes in `incomming`
+    queued: usize,
+    /// payload bytes buffered for reading, datagrams beyond it are dropped
+    recv_buffer: usize,
+    /// packets waiting for the packet loop to send them
+    outgoing: VecDeque<Vec<u8>>,
+    /// bytes in `outgoing`
+    unsent: usize,
+    send_buffer: usize,
+    nonblocking: bool,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+}
+
+impl Socket {
+    /// Queue a datagram received from `src`, `false` if it was dropped.
+    fn on_datagram(&mut self, src: SocketAddr, payload: &[u8]) -> bool {
+        if self.peer.is_some_and(|peer| peer != src)
+            || self.queued + payload.len() > self.recv_buffer
+        {
+            return false;
+        }
+        self.queued += payload.len();
+        self.incomming.push_back((src, payload.to_vec()));
+        true
+    }
+
+    /// Send the queued packets, `true` if there is space for more.
+    pub(crate) fn on_tick(&mut self, nic: &dyn Device) -> io::Result<bool> {
+        let writable = !self.outgoing.is_empty();
+        while let Some(packet) = self.outgoing.pop_front() {
+            nic.send(&packet)?;
+        }
+        self.unsent = 0;
+        Ok(writable)
+    }
+
+    /// Take the next datagram, `None` if the caller has to wait for one.
+    fn try_recv(&mut self, buf: &mut [u8]) -> Option<(usize, SocketAddr)> {
+        let (src, payload) = self.incomming.pop_front()?;
+        self.queued -= payload.len();
+        // Like with `std::net::UdpSocket`, the rest of a long datagram is discarded
+        let n = std::cmp::min(payload.len(), buf.len());
+        buf[..n].copy_from_slice(&payload[..n]);
+        Some((n, src))
+    }
+
+    /// Queue a packet for sending, `false` if the caller has to wait for space.
+    fn try_send(&mut self, packet: &[u8]) -> bool {
+        // A single datagram is always accepted, however small the buffer is
+        if !self.outgoing.is_empty() && self.unsent + packet.len() > self.send_buffer {
+            return false;
+        }
+        self.unsent += packet.len();
+        self.outgoing.push_back(packet.to_vec());
+        true
+    }
+}
+
+impl ConnectionManager {
+    /// The state behind a UDP socket
+    fn udp_socket(&mut self, port: u16) -> io::Result<&mut Socket> {
+        if self.terminate {
+            return Err(shut_down_error());
+        }
+        Ok(self
+            .udp
+            .get_mut(&port)
+            .expect("port closed while socket still active"))
+    }
+}
+
+/// Deliver a UDP datagram to the socket bound to its port, `true` if it was queued.
+pub(crate) fn on_packet(cm: &mut ConnectionManager, iph: &IpSlice) -> bool {
+    let Ok(udp) = UdpSlice::from_slice(iph.payload().payload) else {
+        return false;
+    };
+    let (src, dst) = (iph.source_addr(), iph.destination_addr());
+    let header = udp.to_header();
+    let checksum = match (src, dst) {
+        // The checksum is optional over IPv4
+        (IpAddr::V4(_), IpAddr::V4(_)) if header.checksum == 0 => Ok(0),
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            header.calc_checksum_ipv4_raw(src.octets(), dst.octets(), udp.payload())
+        }
+        (src, dst) => header.calc_checksum_ipv6_raw(
+            to_ipv6(src).octets(),
+            to_ipv6(dst).octets(),
+            udp.payload(),
+        ),
+    };
+    if checksum != Ok(header.checksum) {
+        return false;
+    }
+
+    match cm.udp.get_mut(&udp.destination_port()) {
+        Some(socket) => socket.on_datagram(SocketAddr::new(src, udp.source_port()), udp.payload()),
+        None => false,
+    }
+}
+
+/// A UDP socket bound to a port of an [`Interface`](crate::Interface).
+pub struct UdpSocket {
+    port: u16,
+    h: InterfaceHandle,
+}
+
+impl UdpSocket {
+    /// Bind `port` on the interface behind `h`, or an ephemeral port if it is 0.
+    pub(crate) fn bind(h: &InterfaceHandle, port: u16) -> io::Result<Self> {
+        let mut cm = h.manager.lock().unwrap();
+        if cm.terminate {
+            return Err(shut_down_error());
+        }
+        let port = match port {
+            0 => find_ephemeral_port(|port| !cm.udp.contains_key(&port))?,
+            port if cm.udp.contains_key(&port) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    "port already bound",
+                ));
+            }
+            port => port,
+        };
+        let socket = Socket {
+            peer: None,
+            incomming: VecDeque::new(),
+            queued: 0,
+            recv_buffer: cm.config.recv_buffer,
+            outgoing: VecDeque::new(),
+            unsent: 0,
+            send_buffer: cm.config.send_buffer,
+            nonblocking: false,
+            read_timeout: None,
+            write_timeout: None,
+        };
+        cm.udp.insert(port, socket);
+
+        Ok(UdpSocket { port, h: h.clone() })
+    }
+
+    /// The port this socket is bound to, on every address of the interface.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), self.port))
+    }
+
+    /// Address this socket is connected to.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        let mut cm = self.h.manager.lock().unwrap();
+        cm.udp_socket(self.port)?
+            .peer
+            .ok_or_else(|| io::ErrorKind::NotConnected.into())
+    }
+
+    /// Send to and only receive from `addr`.
+    ///
+    /// Datagrams from other senders that are already queued are discarded.
+    pub fn connect(&self, addr: impl Into<SocketAddr>) -> io::Result<()> {
+        let addr = addr.into();
+        let mut cm = self.h.manager.lock().unwrap();
+        let socket = cm.udp_socket(self.port)?;
+        socket.peer = Some(addr);
+        socket.incomming.retain(|(src, _)| *src == addr);
+        socket.queued = socket.incomming.iter().map(|(_, p)| p.len()).sum();
+        Ok(())
+    }
+
+    /// Send a datagram to `addr`, it has to fit into a single packet.
+    pub fn send_to(&self, buf: &[u8], addr: impl Into<SocketAddr>) -> io::Result<usize> {
+        let remote = addr.into();
+        let mut cm = self.h.manager.lock().unwrap();
+        let local = SocketAddr::new(local_ip(&cm.addrs, remote.ip())?, self.port);
+        let builder = match (local.ip(), remote.ip()) {
+            (IpAddr::V4(local), IpAddr::V4(remote)) => {
+                PacketBuilder::ipv4(local.octets(), remote.octets(), TTL)
+            }
+            (local, remote) => {
+                PacketBuilder::ipv6(to_ipv6(local).octets(), to_ipv6(remote).octets(), TTL)
+            }
+        }
+        .udp(local.port(), remote.port());
+
+        // Fragmentation is not supported
+        if builder.size(buf.len()) > cm.config.mtu {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "datagram does not fit into a packet",
+            ));
+        }
+        let mut packet = Vec::with_capacity(builder.size(buf.len()));
+        builder
+            .write(&mut packet, buf)
+            .expect("failed to build datagram");
+
+        let deadline = cm.udp_socket(self.port)?.write_timeout.and_then(deadline);
+        loop {
+            let socket = cm.udp_socket(self.port)?;
+            if socket.try_send(&packet) {
+                return Ok(buf.len());
+            }
+            if socket.nonblocking {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+
+            cm = wait_until(&self.h.snd_var, cm, deadline)?;
+        }
+    }
+
+    /// Receive a datagram and its sender, the part that does not fit into `buf` is lost.
+    pub fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let mut cm = self.h.manager.lock().unwrap();
+        let deadline = cm.udp_socket(self.port)?.read_timeout.and_then(deadline);
+        loop {
+            let socket = cm.udp_socket(self.port)?;
+            if let Some(received) = socket.try_recv(buf) {
+                return Ok(received);
+            }
+            if socket.nonblocking {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+
+            cm = wait_until(&self.h.rcv_var, cm, deadline)?;
+        }
+    }
+
+    /// Send a datagram to the connected address.
+    pub fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        self.send_to(buf, self.peer_addr()?)
+    }
+
+    /// Receive a datagram, only from the connected address once connected.
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.recv_from(buf).map(|(n, _)| n)
+    }
+
+    /// Largest size of the received datagrams queued for reading, in bytes of payload.
+    pub fn set_recv_buffer_size(&self, size: usize) -> io::Result<()> {
+        let mut cm = self.h.manager.lock().unwrap();
+        cm.udp_socket(self.port)?.recv_buffer = size;
+        Ok(())
+    }
+
+    /// How long a receive waits before failing with `TimedOut`, `None` waits forever.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        check_timeout(timeout)?;
+        let mut cm = self.h.manager.lock().unwrap();
+        cm.udp_socket(self.port)?.read_timeout = timeout;
+        Ok(())
+    }
+
+    /// How long a send waits for buffer space before failing with `TimedOut`, `None`
+    /// waits forever.
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        check_timeout(timeout)?;
+        let mut cm = self.h.manager.lock().unwrap();
+        cm.udp_socket(self.port)?.write_timeout = timeout;
+        Ok(())
+    }
+
+    pub fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        let mut cm = self.h.manager.lock().unwrap();
+        Ok(cm.udp_socket(self.port)?.read_timeout)
+    }
+
+    pub fn write_timeout(&self) -> io::Result<Option<Duration>> {
+        let mut cm = self.h.manager.lock().unwrap();
+        Ok(cm.udp_socket(self.port)?.write_timeout)
+    }
+
+    /// Make sends and receives fail with `WouldBlock` instead of waiting.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        let mut cm = self.h.manager.lock().unwrap();
+        cm.udp_socket(self.port)?.nonblocking = nonblocking;
+        Ok(())
+    }
+}
+
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        let mut cm = self.h.manager.lock().unwrap();
+        cm.udp.remove(&self.port);
+    }
+}
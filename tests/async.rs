@@ -0,0 +1,132 @@
+#![cfg(feature = "async")]
+
+use std::{
+    future::Future,
+    io::{Read, Write},
+    net::SocketAddr,
+    pin::pin,
+    sync::Arc,
+    task::{Context, Poll, Wake},
+    thread::{self, Thread},
+};
+
+mod common;
+
+use common::link;
+
+/// Wakes the thread that polls the future
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = Arc::new(Unpark(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        thread::park();
+    }
+}
+
+const SERVER: ([u8; 4], u16) = ([10, 0, 0, 1], 80);
+
+#[test]
+fn accept_async_waits_for_a_connection() {
+    let (mut server, mut client) = link();
+    let mut listener = server.bind(80).unwrap();
+
+    let connector = thread::spawn(move || {
+        let mut stream = client.connect(SocketAddr::from(SERVER)).unwrap();
+        stream.write_all(b"hello").unwrap();
+        stream.shutdown().unwrap();
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).unwrap();
+    });
+
+    let mut stream = block_on(listener.accept_async()).unwrap();
+    let mut received = Vec::new();
+    stream.read_to_end(&mut received).unwrap();
+    assert_eq!(received, b"hello");
+    drop(stream);
+    connector.join().unwrap();
+}
+
+#[cfg(feature = "futures-io")]
+#[test]
+fn futures_io_read_and_write() {
+    use std::{future::poll_fn, pin::Pin};
+
+    use futures_io::{AsyncRead, AsyncWrite};
+
+    let (mut server, mut client) = link();
+    let mut listener = server.bind(80).unwrap();
+
+    let echo = thread::spawn(move || {
+        let mut stream = listener.accept().unwrap();
+        let mut data = Vec::new();
+        stream.read_to_end(&mut data).unwrap();
+        stream.write_all(&data).unwrap();
+        stream.shutdown().unwrap();
+        server
+    });
+
+    let mut stream = client.connect(SocketAddr::from(SERVER)).unwrap();
+    let data = vec![9; 100_000];
+    let received = block_on(async {
+        let mut sent = 0;
+        while sent < data.len() {
+            sent += poll_fn(|cx| Pin::new(&mut stream).poll_write(cx, &data[sent..]))
+                .await
+                .unwrap();
+        }
+        poll_fn(|cx| Pin::new(&mut stream).poll_flush(cx))
+            .await
+            .unwrap();
+        poll_fn(|cx| Pin::new(&mut stream).poll_close(cx))
+            .await
+            .unwrap();
+
+        let mut received = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            let n = poll_fn(|cx| Pin::new(&mut stream).poll_read(cx, &mut buf))
+                .await
+                .unwrap();
+            if n == 0 {
+                break received;
+            }
+            received.extend_from_slice(&buf[..n]);
+        }
+    });
+    assert!(received == data);
+    let _server = echo.join().unwrap();
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn tokio_read_fills_the_buffer() {
+    use std::{future::poll_fn, pin::Pin};
+
+    use tokio::io::{AsyncRead, ReadBuf};
+
+    let (mut server, mut client) = link();
+    let mut listener = server.bind(80).unwrap();
+    let mut stream = client.connect(SocketAddr::from(SERVER)).unwrap();
+    let mut accepted = listener.accept().unwrap();
+
+    let reader = thread::spawn(move || {
+        let mut buf = [0; 16];
+        let mut buf = ReadBuf::new(&mut buf);
+        block_on(poll_fn(|cx| Pin::new(&mut stream).poll_read(cx, &mut buf))).unwrap();
+        buf.filled().to_vec()
+    });
+    accepted.write_all(b"tokio").unwrap();
+    assert_eq!(reader.join().unwrap(), b"tokio");
+}
@@ -0,0 +1,45 @@
+use std::{io::Read, thread, time::Duration};
+
+mod common;
+
+use common::Peer;
+
+const WAIT: Duration = Duration::from_secs(1);
+
+#[test]
+fn fin_wait2_times_out_only_when_orphaned() {
+    let (mut iface, peer) = Peer::new(|b| b.fin_timeout(Duration::from_millis(200)));
+    let mut listener = iface.bind(80).unwrap();
+    let (mut seq, _) = peer.connect(80, 1000);
+    let mut stream = listener.accept_timeout(WAIT).unwrap();
+
+    stream.shutdown().unwrap();
+    let (fin, _) = peer.recv(WAIT).unwrap();
+    assert!(fin.fin);
+    let rcv_nxt = fin.sequence_number.wrapping_add(1);
+    let mut ack = peer.header(80, seq, 65535);
+    ack.ack = true;
+    ack.acknowledgment_number = rcv_nxt;
+    peer.send(ack.clone(), &[]);
+
+    // In FIN-WAIT-2 the stream keeps receiving past the timeout
+    for _ in 0..3 {
+        thread::sleep(Duration::from_millis(150));
+        ack.sequence_number = seq;
+        peer.send(ack.clone(), b"data");
+        seq = seq.wrapping_add(4);
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"data");
+    }
+
+    // Once dropped, the connection is reset after the timeout
+    drop(stream);
+    let rst = loop {
+        let (segment, _) = peer.recv(WAIT).expect("no RST");
+        if segment.rst {
+            break segment;
+        }
+    };
+    assert_eq!(rst.sequence_number, rcv_nxt);
+}
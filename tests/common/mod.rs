@@ -0,0 +1,141 @@
+//! Helpers shared by the integration tests.
+#![allow(dead_code)]
+
+use std::{io, net::Ipv4Addr, time::Duration};
+
+use crust::{Device, Interface, InterfaceBuilder, PipeDevice};
+use etherparse::{PacketBuilder, SlicedPacket, TcpHeader, TcpOptionElement, TransportSlice};
+
+pub const SERVER: [u8; 4] = [10, 0, 0, 1];
+pub const CLIENT: [u8; 4] = [10, 0, 0, 2];
+
+/// Two interfaces linked by a pipe, 10.0.0.1 and 10.0.0.2
+pub fn link() -> (Interface<PipeDevice>, Interface<PipeDevice>) {
+    link_with(|b| b, |b| b)
+}
+
+/// Like [`link`], the closures adjust the builders of the server and the client.
+pub fn link_with(
+    server: impl FnOnce(InterfaceBuilder) -> InterfaceBuilder,
+    client: impl FnOnce(InterfaceBuilder) -> InterfaceBuilder,
+) -> (Interface<PipeDevice>, Interface<PipeDevice>) {
+    let (a, b) = PipeDevice::pair(1500);
+    let server = server(InterfaceBuilder::new().ipv4(Ipv4Addr::from(SERVER), 24))
+        .build_with_device(a)
+        .unwrap();
+    let client = client(InterfaceBuilder::new().ipv4(Ipv4Addr::from(CLIENT), 24))
+        .build_with_device(b)
+        .unwrap();
+    (server, client)
+}
+
+/// A hand driven TCP endpoint at 10.0.0.2 that talks to an interface at 10.0.0.1.
+pub struct Peer {
+    dev: PipeDevice,
+    pub port: u16,
+}
+
+impl Peer {
+    /// Interface at 10.0.0.1 (adjusted by `f`) and a raw peer connected to it
+    pub fn new(
+        f: impl FnOnce(InterfaceBuilder) -> InterfaceBuilder,
+    ) -> (Interface<PipeDevice>, Peer) {
+        let (a, b) = PipeDevice::pair(1500);
+        let iface = f(InterfaceBuilder::new().ipv4(Ipv4Addr::from(SERVER), 24))
+            .build_with_device(a)
+            .unwrap();
+        (iface, Peer { dev: b, port: 5000 })
+    }
+
+    /// Header from the peer's port to `port` with all flags cleared
+    pub fn header(&self, port: u16, seq: u32, window: u16) -> TcpHeader {
+        TcpHeader::new(self.port, port, seq, window)
+    }
+
+    pub fn send(&self, tcp: TcpHeader, payload: &[u8]) {
+        let builder = PacketBuilder::ipv4(CLIENT, SERVER, 64).tcp_header(tcp);
+        let mut packet = Vec::with_capacity(builder.size(payload.len()));
+        builder.write(&mut packet, payload).unwrap();
+        self.dev.send(&packet).unwrap();
+    }
+
+    /// Next TCP segment sent to the peer, other packets are skipped
+    pub fn recv(&self, timeout: Duration) -> Option<(TcpHeader, Vec<u8>)> {
+        let mut buf = [0; 1500];
+        loop {
+            let n = match self.dev.recv_timeout(&mut buf, timeout) {
+                Ok(n) => n,
+                Err(_) => return None,
+            };
+            let packet = SlicedPacket::from_ip(&buf[..n]).unwrap();
+            if let Some(TransportSlice::Tcp(tcp)) = packet.transport {
+                return Some((tcp.to_header(), tcp.payload().to_vec()));
+            }
+        }
+    }
+
+    /// Open a connection to `port`, returns SND.NXT and RCV.NXT of the peer
+    pub fn connect(&self, port: u16, seq: u32) -> (u32, u32) {
+        let (seq, rcv_nxt, _) = self.handshake(port, seq, &[]);
+        (seq, rcv_nxt)
+    }
+
+    /// Like [`Peer::connect`] with `options` in the SYN, the SYN-ACK is returned as well
+    pub fn handshake(
+        &self,
+        port: u16,
+        seq: u32,
+        options: &[TcpOptionElement],
+    ) -> (u32, u32, TcpHeader) {
+        let mut syn = self.header(port, seq, 65535);
+        syn.syn = true;
+        syn.set_options(options).unwrap();
+        self.send(syn, &[]);
+        let (syn_ack, _) = self.recv(Duration::from_secs(1)).expect("no SYN-ACK");
+        assert!(syn_ack.syn && syn_ack.ack);
+        let rcv_nxt = syn_ack.sequence_number.wrapping_add(1);
+        let mut ack = self.header(port, seq.wrapping_add(1), 65535);
+        ack.ack = true;
+        ack.acknowledgment_number = rcv_nxt;
+        self.send(ack, &[]);
+        (seq.wrapping_add(1), rcv_nxt, syn_ack)
+    }
+}
+
+/// A device that loses one in `nth` packets it sends, at random.
+pub struct LossyDevice {
+    inner: PipeDevice,
+    nth: u32,
+}
+
+impl Device for LossyDevice {
+    fn send(&self, packet: &[u8]) -> io::Result<usize> {
+        if rand::random_ratio(1, self.nth) {
+            return Ok(packet.len());
+        }
+        self.inner.send(packet)
+    }
+
+    fn recv_timeout(&self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        self.inner.recv_timeout(buf, timeout)
+    }
+
+    fn mtu(&self) -> io::Result<u16> {
+        self.inner.mtu()
+    }
+}
+
+/// Like [`link`], but the server loses one in `nth` packets it sends.
+pub fn lossy_link(nth: u32) -> (Interface<LossyDevice>, Interface<PipeDevice>) {
+    let (a, b) = PipeDevice::pair(1500);
+    let a = LossyDevice { inner: a, nth };
+    let server = InterfaceBuilder::new()
+        .ipv4(Ipv4Addr::from(SERVER), 24)
+        .build_with_device(a)
+        .unwrap();
+    let client = InterfaceBuilder::new()
+        .ipv4(Ipv4Addr::from(CLIENT), 24)
+        .build_with_device(b)
+        .unwrap();
+    (server, client)
+}
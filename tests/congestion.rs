@@ -0,0 +1,59 @@
+use std::{io::Write, time::Duration};
+
+mod common;
+
+use common::Peer;
+
+const WAIT: Duration = Duration::from_secs(1);
+
+/// Every segment the peer receives until the link stays quiet for a while
+fn drain(peer: &Peer) -> Vec<(u32, usize)> {
+    let mut segments = Vec::new();
+    while let Some((tcp, payload)) = peer.recv(Duration::from_millis(100)) {
+        segments.push((tcp.sequence_number, payload.len()));
+    }
+    segments
+}
+
+#[test]
+fn initial_window_limits_the_first_flight() {
+    let (mut iface, peer) = Peer::new(|b| b);
+    let mut listener = iface.bind(80).unwrap();
+    // No MSS option, the peer's MSS is 536
+    peer.connect(80, 1000);
+    let mut stream = listener.accept_timeout(WAIT).unwrap();
+    stream.write_all(&[0; 20_000]).unwrap();
+
+    // min(4 * SMSS, max(2 * SMSS, 4380)) bytes (RFC 5681, 3.1)
+    let sent: usize = drain(&peer).iter().map(|&(_, len)| len).sum();
+    assert_eq!(sent, 4 * 536);
+}
+
+#[test]
+fn third_duplicate_ack_triggers_fast_retransmit() {
+    let (mut iface, peer) = Peer::new(|b| b);
+    let mut listener = iface.bind(80).unwrap();
+    let (seq, rcv_nxt) = peer.connect(80, 1000);
+    let mut stream = listener.accept_timeout(WAIT).unwrap();
+    stream.write_all(&[0; 20_000]).unwrap();
+
+    let flight = drain(&peer);
+    let (first, len) = flight[0];
+    assert_eq!(first, rcv_nxt);
+
+    // The first segment arrived, the second was lost
+    let mut ack = peer.header(80, seq, 65535);
+    ack.ack = true;
+    ack.acknowledgment_number = first.wrapping_add(len as u32);
+    peer.send(ack.clone(), &[]);
+    drain(&peer);
+    for _ in 0..3 {
+        peer.send(ack.clone(), &[]);
+    }
+
+    // Resent right away instead of after the retransmission timeout
+    let (tcp, _) = peer
+        .recv(Duration::from_millis(300))
+        .expect("no fast retransmit");
+    assert_eq!(tcp.sequence_number, ack.acknowledgment_number);
+}
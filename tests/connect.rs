@@ -0,0 +1,32 @@
+use std::{io::ErrorKind, net::SocketAddr, time::Duration};
+
+mod common;
+
+use common::{Peer, link};
+
+#[test]
+fn connect_timeout_expires() {
+    // The raw peer never answers
+    let (mut iface, _peer) = Peer::new(|b| b);
+    let err = iface
+        .connect_timeout(
+            SocketAddr::from(([10, 0, 0, 2], 80)),
+            Duration::from_millis(100),
+        )
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+}
+
+#[test]
+fn huge_connect_timeout_means_no_deadline() {
+    let (mut server, mut client) = link();
+    let _listener = server.bind(80).unwrap();
+    let stream = client
+        .connect_timeout(SocketAddr::from(([10, 0, 0, 1], 80)), Duration::MAX)
+        .unwrap();
+    assert_eq!(
+        stream.peer_addr().unwrap(),
+        SocketAddr::from(([10, 0, 0, 1], 80))
+    );
+}
@@ -0,0 +1,81 @@
+use std::{io::Read, time::Duration};
+
+mod common;
+
+use common::Peer;
+use etherparse::TcpOptionElement;
+
+const WAIT: Duration = Duration::from_secs(1);
+
+#[test]
+fn cookie_handshake_completes() {
+    let (mut iface, mut peer) = Peer::new(|b| b);
+    let mut listener = iface.bind_with_backlog(80, 2).unwrap();
+
+    // Fill the SYN queue
+    for port in [1001, 1002] {
+        peer.port = port;
+        let mut syn = peer.header(80, 100, 65535);
+        syn.syn = true;
+        peer.send(syn, &[]);
+        peer.recv(WAIT).unwrap();
+    }
+
+    peer.port = 1003;
+    let mut syn = peer.header(80, 500, 65535);
+    syn.syn = true;
+    syn.set_options(&[
+        TcpOptionElement::MaximumSegmentSize(1400),
+        TcpOptionElement::WindowScale(7),
+        TcpOptionElement::SelectiveAcknowledgementPermitted,
+        TcpOptionElement::Timestamp(5, 0),
+    ])
+    .unwrap();
+    peer.send(syn, &[]);
+    let (syn_ack, _) = peer.recv(WAIT).unwrap();
+    assert!(syn_ack.syn && syn_ack.ack);
+    assert_eq!(syn_ack.acknowledgment_number, 501);
+    let options: Vec<_> = syn_ack.options_iterator().map(|o| o.unwrap()).collect();
+    assert!(matches!(
+        options[0],
+        TcpOptionElement::MaximumSegmentSize(_)
+    ));
+    assert!(
+        options
+            .iter()
+            .any(|o| matches!(o, TcpOptionElement::WindowScale(_)))
+    );
+    assert!(options.contains(&TcpOptionElement::SelectiveAcknowledgementPermitted));
+    // Timestamps do not fit into the cookie
+    assert!(
+        !options
+            .iter()
+            .any(|o| matches!(o, TcpOptionElement::Timestamp(..)))
+    );
+    assert_eq!(iface.syn_stats().cookies_sent, 1);
+
+    let mut ack = peer.header(80, 501, 512);
+    ack.ack = true;
+    ack.acknowledgment_number = syn_ack.sequence_number.wrapping_add(1);
+    peer.send(ack, b"hi");
+    let mut stream = listener.accept_timeout(WAIT).unwrap();
+    let mut buf = [0; 2];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hi");
+    assert_eq!(iface.syn_stats().cookies_accepted, 1);
+}
+
+#[test]
+fn ack_is_reset_while_no_cookies_are_out() {
+    let (mut iface, peer) = Peer::new(|b| b);
+    let _listener = iface.bind(80).unwrap();
+
+    let mut ack = peer.header(80, 501, 512);
+    ack.ack = true;
+    ack.acknowledgment_number = 0x1234_5678;
+    peer.send(ack, b"hi");
+    let (rst, _) = peer.recv(WAIT).unwrap();
+    assert!(rst.rst);
+    assert_eq!(rst.sequence_number, 0x1234_5678);
+    assert_eq!(iface.syn_stats().cookies_accepted, 0);
+}
@@ -0,0 +1,71 @@
+use std::{
+    io::{Read, Write},
+    net::SocketAddr,
+    thread,
+    time::Duration,
+};
+
+mod common;
+
+use common::{Peer, link};
+
+#[test]
+fn late_reader_receives_everything() {
+    let (mut server, mut client) = link();
+    let mut listener = server.bind(80).unwrap();
+    let data: Vec<u8> = (0..1 << 20).map(|i| (i % 251) as u8).collect();
+
+    let sent = data.clone();
+    let writer = thread::spawn(move || {
+        let mut stream = client
+            .connect(SocketAddr::from(([10, 0, 0, 1], 80)))
+            .unwrap();
+        stream
+            .set_write_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        stream.write_all(&sent).unwrap();
+        stream.shutdown().unwrap();
+        // Keep the interface up until the reader is done
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).unwrap();
+    });
+
+    let mut stream = listener.accept().unwrap();
+    // The receive buffer fills up and the window closes
+    thread::sleep(Duration::from_secs(1));
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    let mut received = Vec::new();
+    stream.read_to_end(&mut received).unwrap();
+    assert!(received == data, "received {} bytes", received.len());
+    drop(stream);
+    writer.join().unwrap();
+}
+
+#[test]
+fn zero_window_is_probed() {
+    let (mut iface, peer) = Peer::new(|b| b);
+    let mut listener = iface.bind(80).unwrap();
+    let (seq, rcv_nxt) = peer.connect(80, 1000);
+    let mut stream = listener.accept_timeout(Duration::from_secs(1)).unwrap();
+
+    // Close the window before anything is sent
+    let mut ack = peer.header(80, seq, 0);
+    ack.ack = true;
+    ack.acknowledgment_number = rcv_nxt;
+    peer.send(ack.clone(), &[]);
+    thread::sleep(Duration::from_millis(50));
+    stream.write_all(b"held back").unwrap();
+
+    // <SEQ=SND.UNA-1> without data, answered with an open window
+    let (probe, payload) = peer.recv(Duration::from_secs(3)).expect("no probe");
+    assert!(payload.is_empty());
+    assert_eq!(probe.sequence_number, rcv_nxt.wrapping_sub(1));
+    ack.window_size = 65535;
+    peer.send(ack, &[]);
+
+    let (segment, payload) = peer.recv(Duration::from_secs(1)).unwrap();
+    assert_eq!(segment.sequence_number, rcv_nxt);
+    assert_eq!(payload, b"held back");
+}
@@ -0,0 +1,75 @@
+use std::{io::Read, time::Duration};
+
+mod common;
+
+use common::Peer;
+
+const WAIT: Duration = Duration::from_secs(1);
+
+#[test]
+fn ack_of_the_iss_is_reset() {
+    let (mut iface, peer) = Peer::new(|b| b);
+    let mut listener = iface.bind(80).unwrap();
+
+    let mut syn = peer.header(80, 1000, 65535);
+    syn.syn = true;
+    peer.send(syn, &[]);
+    let (syn_ack, _) = peer.recv(WAIT).unwrap();
+    let iss = syn_ack.sequence_number;
+
+    // SEG.ACK == SND.UNA does not acknowledge the SYN
+    let mut ack = peer.header(80, 1001, 65535);
+    ack.ack = true;
+    ack.acknowledgment_number = iss;
+    peer.send(ack.clone(), b"x");
+    let (rst, _) = peer.recv(WAIT).unwrap();
+    assert!(rst.rst);
+    assert_eq!(rst.sequence_number, iss);
+
+    // The connection is still half-open and completes with the right ACK
+    ack.acknowledgment_number = iss.wrapping_add(1);
+    peer.send(ack, b"x");
+    let mut stream = listener.accept_timeout(WAIT).unwrap();
+    let mut buf = [0; 1];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"x");
+}
+
+#[test]
+fn duplicate_syn_repeats_the_syn_ack() {
+    let (mut iface, peer) = Peer::new(|b| b);
+    let _listener = iface.bind(80).unwrap();
+
+    let mut syn = peer.header(80, 1000, 65535);
+    syn.syn = true;
+    peer.send(syn.clone(), &[]);
+    let (first, _) = peer.recv(WAIT).unwrap();
+    peer.send(syn, &[]);
+    let (second, _) = peer.recv(WAIT).unwrap();
+    assert!(second.syn && second.ack && !second.rst);
+    assert_eq!(second.sequence_number, first.sequence_number);
+    assert_eq!(second.acknowledgment_number, 1001);
+}
+
+#[test]
+fn unanswered_syn_ack_is_abandoned() {
+    let (mut iface, peer) = Peer::new(|b| b.syn_retries(1));
+    let _listener = iface.bind(80).unwrap();
+
+    let mut syn = peer.header(80, 1000, 65535);
+    syn.syn = true;
+    peer.send(syn, &[]);
+    let (first, _) = peer.recv(WAIT).unwrap();
+    let (again, _) = peer.recv(Duration::from_secs(2)).unwrap();
+    assert!(again.syn && again.ack);
+    assert_eq!(again.sequence_number, first.sequence_number);
+
+    // After the last retry the half-open connection is gone
+    assert!(peer.recv(Duration::from_secs(3)).is_none());
+    let mut ack = peer.header(80, 1001, 65535);
+    ack.ack = true;
+    ack.acknowledgment_number = first.sequence_number.wrapping_add(1);
+    peer.send(ack, &[]);
+    let (rst, _) = peer.recv(WAIT).unwrap();
+    assert!(rst.rst);
+}
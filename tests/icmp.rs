@@ -0,0 +1,39 @@
+use std::{
+    io::ErrorKind,
+    net::{IpAddr, Ipv4Addr},
+    time::Duration,
+};
+
+mod common;
+
+use common::{Peer, link};
+
+#[test]
+fn ping_gets_a_reply() {
+    let (_server, mut client) = link();
+    let rtt = client
+        .ping(Ipv4Addr::new(10, 0, 0, 1), b"hello", Duration::from_secs(1))
+        .unwrap();
+    assert!(rtt < Duration::from_secs(1));
+}
+
+#[test]
+fn huge_ping_timeout_means_no_deadline() {
+    let (_server, mut client) = link();
+    client
+        .ping(IpAddr::from([10, 0, 0, 1]), b"hello", Duration::MAX)
+        .unwrap();
+}
+
+#[test]
+fn unanswered_ping_times_out() {
+    let (mut iface, _peer) = Peer::new(|b| b);
+    let err = iface
+        .ping(
+            Ipv4Addr::new(10, 0, 0, 2),
+            b"hello",
+            Duration::from_millis(100),
+        )
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+}
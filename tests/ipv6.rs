@@ -0,0 +1,64 @@
+use std::{
+    io::{Read, Write},
+    net::{Ipv6Addr, SocketAddr},
+};
+
+mod common;
+
+use common::link_with;
+use crust::{Interface, PipeDevice};
+
+const SERVER6: Ipv6Addr = Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1);
+const CLIENT6: Ipv6Addr = Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2);
+
+fn dual_stack() -> (Interface<PipeDevice>, Interface<PipeDevice>) {
+    link_with(|b| b.ipv6(SERVER6, 64), |b| b.ipv6(CLIENT6, 64))
+}
+
+#[test]
+fn tcp_over_ipv6() {
+    let (mut server, mut client) = dual_stack();
+    let mut listener = server.bind(80).unwrap();
+
+    let mut stream = client.connect(SocketAddr::from((SERVER6, 80))).unwrap();
+    assert_eq!(stream.peer_addr().unwrap(), SocketAddr::from((SERVER6, 80)));
+    assert_eq!(stream.local_addr().unwrap().ip(), CLIENT6);
+    let mut accepted = listener.accept().unwrap();
+    assert_eq!(accepted.peer_addr().unwrap().ip(), CLIENT6);
+
+    stream.write_all(&[6; 10_000]).unwrap();
+    stream.shutdown().unwrap();
+    let mut received = Vec::new();
+    accepted.read_to_end(&mut received).unwrap();
+    assert_eq!(received, [6; 10_000]);
+}
+
+#[test]
+fn listener_accepts_both_families() {
+    let (mut server, mut client) = dual_stack();
+    let mut listener = server.bind(80).unwrap();
+
+    let _v4 = client
+        .connect(SocketAddr::from(([10, 0, 0, 1], 80)))
+        .unwrap();
+    let _v6 = client.connect(SocketAddr::from((SERVER6, 80))).unwrap();
+    let mut peers = [
+        listener.accept().unwrap().peer_addr().unwrap(),
+        listener.accept().unwrap().peer_addr().unwrap(),
+    ];
+    peers.sort_by_key(|addr| addr.is_ipv6());
+    assert!(peers[0].is_ipv4() && peers[1].is_ipv6());
+}
+
+#[test]
+fn udp_over_ipv6() {
+    let (mut server, mut client) = dual_stack();
+    let a = server.bind_udp(53).unwrap();
+    let b = client.bind_udp(0).unwrap();
+    b.send_to(b"query", SocketAddr::from((SERVER6, 53)))
+        .unwrap();
+    let mut buf = [0; 16];
+    let (n, from) = a.recv_from(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"query");
+    assert_eq!(from.ip(), CLIENT6);
+}
@@ -0,0 +1,59 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    thread,
+    time::Duration,
+};
+
+mod common;
+
+use common::{Peer, link};
+use crust::Keepalive;
+
+#[test]
+fn unanswered_probes_abort_the_connection() {
+    let (mut iface, peer) = Peer::new(|b| b);
+    let mut listener = iface.bind(80).unwrap();
+    let (_, rcv_nxt) = peer.connect(80, 1000);
+    let mut stream = listener.accept_timeout(Duration::from_secs(1)).unwrap();
+    stream
+        .set_keepalive(Some(Keepalive {
+            idle: Duration::from_millis(50),
+            interval: Duration::from_millis(50),
+            probes: 2,
+        }))
+        .unwrap();
+
+    // <SEQ=SND.NXT-1>, twice, then the connection is reset
+    for _ in 0..2 {
+        let (probe, payload) = peer.recv(Duration::from_secs(1)).unwrap();
+        assert!(probe.ack && !probe.rst && payload.is_empty());
+        assert_eq!(probe.sequence_number, rcv_nxt.wrapping_sub(1));
+    }
+    let (rst, _) = peer.recv(Duration::from_secs(1)).unwrap();
+    assert!(rst.rst);
+
+    let err = stream.read(&mut [0; 16]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+}
+
+#[test]
+fn huge_keepalive_times_never_fire() {
+    let (mut server, mut client) = link();
+    let mut listener = server.bind(80).unwrap();
+    let mut stream = client.connect(([10, 0, 0, 1], 80)).unwrap();
+    let mut accepted = listener.accept().unwrap();
+    stream
+        .set_keepalive(Some(Keepalive {
+            idle: Duration::MAX,
+            interval: Duration::MAX,
+            probes: u32::MAX,
+        }))
+        .unwrap();
+
+    // A few ticks pass on the idle connection
+    thread::sleep(Duration::from_millis(50));
+    stream.write_all(b"still alive").unwrap();
+    let mut buf = [0; 11];
+    accepted.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"still alive");
+}
@@ -0,0 +1,60 @@
+use std::{io::ErrorKind, time::Duration};
+
+mod common;
+
+use common::Peer;
+use crust::ListenOverflow;
+
+const WAIT: Duration = Duration::from_secs(1);
+
+/// Send a SYN from `port`, returns the answer
+fn syn(peer: &mut Peer, port: u16) -> Option<etherparse::TcpHeader> {
+    peer.port = port;
+    let mut syn = peer.header(80, 1000, 65535);
+    syn.syn = true;
+    peer.send(syn, &[]);
+    peer.recv(Duration::from_millis(200)).map(|(tcp, _)| tcp)
+}
+
+#[test]
+fn only_established_connections_are_accepted() {
+    let (mut iface, mut peer) = Peer::new(|b| b);
+    let mut listener = iface.bind(80).unwrap();
+
+    let syn_ack = syn(&mut peer, 5000).unwrap();
+    assert!(syn_ack.syn && syn_ack.ack);
+    let err = listener
+        .accept_timeout(Duration::from_millis(100))
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+
+    let mut ack = peer.header(80, 1001, 65535);
+    ack.ack = true;
+    ack.acknowledgment_number = syn_ack.sequence_number.wrapping_add(1);
+    peer.send(ack, &[]);
+    listener.accept_timeout(WAIT).unwrap();
+}
+
+#[test]
+fn full_backlog_resets_when_asked_to() {
+    let (mut iface, mut peer) = Peer::new(|b| b);
+    let listener = iface.bind_with_backlog(80, 1).unwrap();
+    listener.set_syn_cookies(false).unwrap();
+    listener.set_overflow(ListenOverflow::Reset).unwrap();
+
+    assert!(syn(&mut peer, 5001).unwrap().syn);
+    assert!(syn(&mut peer, 5002).unwrap().rst);
+    assert_eq!(iface.syn_stats().overflowed, 1);
+}
+
+#[test]
+fn full_backlog_drops_by_default() {
+    let (mut iface, mut peer) = Peer::new(|b| b);
+    let listener = iface.bind_with_backlog(80, 1).unwrap();
+    listener.set_syn_cookies(false).unwrap();
+
+    assert!(syn(&mut peer, 5001).unwrap().syn);
+    assert!(syn(&mut peer, 5002).is_none());
+    assert_eq!(iface.syn_stats().overflowed, 1);
+}
@@ -0,0 +1,75 @@
+use std::{
+    io::{Read, Write},
+    net::SocketAddr,
+    thread,
+    time::Duration,
+};
+
+mod common;
+
+use common::{Peer, lossy_link};
+
+#[test]
+fn bulk_transfer_recovers_from_loss() {
+    // 2% of the data segments and retransmissions are lost
+    let (mut server, mut client) = lossy_link(50);
+    let mut listener = server.bind(80).unwrap();
+    let data: Vec<u8> = (0..4 << 20).map(|i| (i % 251) as u8).collect();
+
+    let sent = data.clone();
+    let sender = thread::spawn(move || {
+        let mut stream = listener.accept().unwrap();
+        stream.write_all(&sent).unwrap();
+        stream.shutdown().unwrap();
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).unwrap();
+        server
+    });
+
+    let mut stream = client
+        .connect(SocketAddr::from(([10, 0, 0, 1], 80)))
+        .unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(30)))
+        .unwrap();
+    let mut received = Vec::new();
+    stream.read_to_end(&mut received).unwrap();
+    assert!(received == data, "received {} bytes", received.len());
+    drop(stream);
+    let _server = sender.join().unwrap();
+}
+
+#[test]
+fn timeout_goes_back_to_the_first_unacknowledged_byte() {
+    let (mut iface, peer) = Peer::new(|b| b);
+    let mut listener = iface.bind(80).unwrap();
+    // Neither SACK nor window scaling are offered
+    let (seq, rcv_nxt) = peer.connect(80, 1000);
+    let mut stream = listener.accept_timeout(Duration::from_secs(1)).unwrap();
+    stream.write_all(&[7; 4000]).unwrap();
+
+    // The whole flight is lost
+    let mut segments = 0;
+    while let Some((segment, _)) = peer.recv(Duration::from_millis(200)) {
+        assert!(!segment.syn);
+        segments += 1;
+    }
+    assert!(segments > 1);
+
+    // The timeout resends the first segment only
+    let (first, payload) = peer
+        .recv(Duration::from_secs(3))
+        .expect("no retransmission");
+    assert_eq!(first.sequence_number, rcv_nxt);
+    let mut ack = peer.header(80, seq, 65535);
+    ack.ack = true;
+    ack.acknowledgment_number = rcv_nxt.wrapping_add(payload.len() as u32);
+    peer.send(ack, &[]);
+
+    // Its ACK clocks out the rest right away, not after another timeout
+    let (next, _) = peer.recv(Duration::from_millis(500)).expect("no go-back-N");
+    assert_eq!(
+        next.sequence_number,
+        rcv_nxt.wrapping_add(payload.len() as u32)
+    );
+}
@@ -0,0 +1,65 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{Ipv4Addr, SocketAddr},
+    thread,
+};
+
+mod common;
+
+use common::link;
+
+#[test]
+fn tcp_round_trip() {
+    let (mut server, mut client) = link();
+    let mut listener = server.bind(80).unwrap();
+
+    // Echo everything back once the client is done sending
+    let echo = thread::spawn(move || {
+        let mut stream = listener.accept().unwrap();
+        let mut data = Vec::new();
+        stream.read_to_end(&mut data).unwrap();
+        stream.write_all(&data).unwrap();
+        stream.flush().unwrap();
+    });
+
+    let mut stream = client
+        .connect(SocketAddr::from(([10, 0, 0, 1], 80)))
+        .unwrap();
+    assert_eq!(stream.peer_addr().unwrap(), ([10, 0, 0, 1], 80).into());
+
+    let data: Vec<u8> = (0..500_000u32).map(|i| i as u8).collect();
+    stream.write_all(&data).unwrap();
+    stream.shutdown().unwrap();
+
+    let mut echoed = Vec::new();
+    stream.read_to_end(&mut echoed).unwrap();
+    assert!(echoed == data);
+    echo.join().unwrap();
+}
+
+#[test]
+fn connect_to_closed_port_is_refused() {
+    let (_server, mut client) = link();
+    let result = client.connect(SocketAddr::from(([10, 0, 0, 1], 81)));
+    assert_eq!(
+        result.err().map(|e| e.kind()),
+        Some(ErrorKind::ConnectionRefused)
+    );
+}
+
+#[test]
+fn udp_round_trip() {
+    let (mut server, mut client) = link();
+    let server = server.bind_udp(53).unwrap();
+    let client = client.bind_udp(0).unwrap();
+
+    client.send_to(b"ping", ([10, 0, 0, 1], 53)).unwrap();
+    let mut buf = [0; 16];
+    let (n, from) = server.recv_from(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"ping");
+    assert_eq!(from.ip(), Ipv4Addr::new(10, 0, 0, 2));
+
+    server.send_to(b"pong", from).unwrap();
+    let (n, _) = client.recv_from(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"pong");
+}
@@ -0,0 +1,41 @@
+use std::{io::ErrorKind, net::SocketAddr};
+
+mod common;
+
+use common::link;
+
+const EPHEMERAL_PORTS: std::ops::RangeInclusive<u16> = 49152..=65535;
+
+#[test]
+fn udp_ephemeral_ports_are_distinct() {
+    let (mut iface, _client) = link();
+    let a = iface.bind_udp(0).unwrap();
+    let b = iface.bind_udp(0).unwrap();
+    let (a, b) = (
+        a.local_addr().unwrap().port(),
+        b.local_addr().unwrap().port(),
+    );
+    assert!(EPHEMERAL_PORTS.contains(&a) && EPHEMERAL_PORTS.contains(&b));
+    assert_ne!(a, b);
+
+    let err = iface.bind_udp(a).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::AddrInUse);
+}
+
+#[test]
+fn tcp_ephemeral_ports_are_distinct() {
+    let (mut server, mut client) = link();
+    let _listener = server.bind(80).unwrap();
+    let a = client
+        .connect(SocketAddr::from(([10, 0, 0, 1], 80)))
+        .unwrap();
+    let b = client
+        .connect(SocketAddr::from(([10, 0, 0, 1], 80)))
+        .unwrap();
+    let (a, b) = (
+        a.local_addr().unwrap().port(),
+        b.local_addr().unwrap().port(),
+    );
+    assert!(EPHEMERAL_PORTS.contains(&a) && EPHEMERAL_PORTS.contains(&b));
+    assert_ne!(a, b);
+}
@@ -0,0 +1,50 @@
+#![cfg(unix)]
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    os::{fd::AsFd, unix::net::UnixStream},
+    thread,
+    time::Duration,
+};
+
+mod common;
+
+use common::link;
+use crust::{Available, TcpStream};
+
+/// Whether the descriptor of `stream` is signaled, consumes the signal
+fn signaled(stream: &TcpStream) -> bool {
+    let mut fd = UnixStream::from(stream.as_fd().try_clone_to_owned().unwrap());
+    match fd.read(&mut [0; 16]) {
+        Ok(n) => n > 0,
+        Err(e) if e.kind() == ErrorKind::WouldBlock => false,
+        Err(e) => panic!("{e}"),
+    }
+}
+
+#[test]
+fn writable_stream_is_not_signaled_by_default() {
+    let (mut server, mut client) = link();
+    let mut listener = server.bind(80).unwrap();
+    let stream = client.connect(([10, 0, 0, 1], 80)).unwrap();
+    let mut accepted = listener.accept().unwrap();
+
+    assert!(stream.readiness().unwrap().contains(Available::WRITE));
+    assert!(!signaled(&stream));
+
+    accepted.write_all(b"data").unwrap();
+    thread::sleep(Duration::from_millis(50));
+    assert!(signaled(&stream));
+}
+
+#[test]
+fn write_interest_signals_a_writable_stream() {
+    let (mut server, mut client) = link();
+    let _listener = server.bind(80).unwrap();
+    let stream = client.connect(([10, 0, 0, 1], 80)).unwrap();
+
+    stream
+        .set_interest(Available::READ | Available::WRITE)
+        .unwrap();
+    assert!(signaled(&stream));
+}
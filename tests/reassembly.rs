@@ -0,0 +1,67 @@
+use std::{io::Read, time::Duration};
+
+mod common;
+
+use common::Peer;
+
+const WAIT: Duration = Duration::from_secs(1);
+
+#[test]
+fn out_of_order_segments_are_delivered_once_the_gap_fills() {
+    let (mut iface, peer) = Peer::new(|b| b);
+    let mut listener = iface.bind(80).unwrap();
+    let (seq, rcv_nxt) = peer.connect(80, 1000);
+    let mut stream = listener.accept_timeout(WAIT).unwrap();
+
+    let segment = |offset: u32| {
+        let mut tcp = peer.header(80, seq.wrapping_add(offset), 65535);
+        tcp.ack = true;
+        tcp.acknowledgment_number = rcv_nxt;
+        tcp
+    };
+
+    // Future data is kept, the ACKs keep pointing at the gap
+    peer.send(segment(4), b"cc");
+    peer.send(segment(2), b"bb");
+    for _ in 0..2 {
+        let (ack, _) = peer.recv(WAIT).unwrap();
+        assert_eq!(ack.acknowledgment_number, seq);
+    }
+
+    peer.send(segment(0), b"aa");
+    let (ack, _) = peer.recv(WAIT).unwrap();
+    assert_eq!(ack.acknowledgment_number, seq.wrapping_add(6));
+
+    let mut buf = [0; 6];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"aabbcc");
+}
+
+#[test]
+fn overlapping_segments_are_merged() {
+    let (mut iface, peer) = Peer::new(|b| b);
+    let mut listener = iface.bind(80).unwrap();
+    let (seq, rcv_nxt) = peer.connect(80, 1000);
+    let mut stream = listener.accept_timeout(WAIT).unwrap();
+
+    let segment = |offset: u32| {
+        let mut tcp = peer.header(80, seq.wrapping_add(offset), 65535);
+        tcp.ack = true;
+        tcp.acknowledgment_number = rcv_nxt;
+        tcp
+    };
+    peer.send(segment(3), b"defg");
+    peer.send(segment(5), b"fghi");
+    peer.send(segment(0), b"abcd");
+    let ack = loop {
+        let (ack, _) = peer.recv(WAIT).unwrap();
+        if ack.acknowledgment_number != seq {
+            break ack;
+        }
+    };
+    assert_eq!(ack.acknowledgment_number, seq.wrapping_add(9));
+
+    let mut buf = [0; 9];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"abcdefghi");
+}
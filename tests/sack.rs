@@ -0,0 +1,100 @@
+use std::{io::Write, time::Duration};
+
+mod common;
+
+use common::Peer;
+use etherparse::{TcpHeader, TcpOptionElement};
+
+const WAIT: Duration = Duration::from_secs(1);
+
+fn sack_blocks(tcp: &TcpHeader) -> Vec<(u32, u32)> {
+    tcp.options_iterator()
+        .find_map(|o| match o.unwrap() {
+            TcpOptionElement::SelectiveAcknowledgement(first, rest) => Some(
+                std::iter::once(first)
+                    .chain(rest.into_iter().flatten())
+                    .collect(),
+            ),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+#[test]
+fn receiver_reports_out_of_order_and_duplicate_data() {
+    let (mut iface, peer) = Peer::new(|b| b);
+    let _listener = iface.bind(80).unwrap();
+    let (seq, rcv_nxt, syn_ack) = peer.handshake(
+        80,
+        1000,
+        &[TcpOptionElement::SelectiveAcknowledgementPermitted],
+    );
+    assert!(
+        syn_ack
+            .options_iterator()
+            .any(|o| o.unwrap() == TcpOptionElement::SelectiveAcknowledgementPermitted)
+    );
+
+    let segment = |offset: u32| {
+        let mut tcp = peer.header(80, seq.wrapping_add(offset), 65535);
+        tcp.ack = true;
+        tcp.acknowledgment_number = rcv_nxt;
+        tcp
+    };
+    peer.send(segment(10), b"later");
+    let (ack, _) = peer.recv(WAIT).unwrap();
+    assert_eq!(ack.acknowledgment_number, seq);
+    assert_eq!(sack_blocks(&ack), [(seq + 10, seq + 15)]);
+
+    // D-SACK for data that was already received (RFC 2883)
+    peer.send(segment(0), b"first");
+    peer.recv(WAIT).unwrap();
+    peer.send(segment(0), b"first");
+    let (ack, _) = peer.recv(WAIT).unwrap();
+    assert_eq!(ack.acknowledgment_number, seq + 5);
+    assert_eq!(sack_blocks(&ack)[0], (seq, seq + 5));
+}
+
+#[test]
+fn sender_retransmits_only_the_holes() {
+    let (mut iface, peer) = Peer::new(|b| b);
+    let mut listener = iface.bind(80).unwrap();
+    let (seq, rcv_nxt, _) = peer.handshake(
+        80,
+        1000,
+        &[
+            TcpOptionElement::MaximumSegmentSize(500),
+            TcpOptionElement::SelectiveAcknowledgementPermitted,
+        ],
+    );
+    let mut stream = listener.accept_timeout(WAIT).unwrap();
+    stream.write_all(&[0; 2000]).unwrap();
+    let mut flight = Vec::new();
+    while let Some((tcp, payload)) = peer.recv(Duration::from_millis(100)) {
+        flight.push((tcp.sequence_number, payload.len()));
+    }
+    assert_eq!(flight.len(), 4);
+
+    // The first segment is lost, the other three arrive and are SACKed
+    for i in 1..4 {
+        let mut ack = peer.header(80, seq, 65535);
+        ack.ack = true;
+        ack.acknowledgment_number = rcv_nxt;
+        ack.set_options(&[TcpOptionElement::SelectiveAcknowledgement(
+            (rcv_nxt + 500, rcv_nxt + 500 * (i + 1)),
+            [None; 3],
+        )])
+        .unwrap();
+        peer.send(ack, &[]);
+    }
+
+    let (tcp, payload) = peer
+        .recv(Duration::from_millis(300))
+        .expect("no retransmission");
+    assert_eq!(tcp.sequence_number, rcv_nxt);
+    assert_eq!(payload.len(), 500);
+    // Nothing SACKed is sent again
+    while let Some((tcp, _)) = peer.recv(Duration::from_millis(100)) {
+        assert!(tcp.sequence_number >= rcv_nxt + 2000 || tcp.sequence_number == rcv_nxt);
+    }
+}
@@ -0,0 +1,54 @@
+use std::{io::Write, time::Duration};
+
+mod common;
+
+use common::Peer;
+use etherparse::TcpOptionElement;
+
+#[test]
+fn syn_ack_advertises_the_mss_of_the_mtu() {
+    let (mut iface, peer) = Peer::new(|b| b);
+    let _listener = iface.bind(80).unwrap();
+    let (_, _, syn_ack) = peer.handshake(80, 1000, &[]);
+    let mss = syn_ack
+        .options_iterator()
+        .find_map(|o| match o.unwrap() {
+            TcpOptionElement::MaximumSegmentSize(mss) => Some(mss),
+            _ => None,
+        })
+        .expect("no MSS option");
+    // 1500 bytes minus the IPv4 and TCP headers
+    assert_eq!(mss, 1460);
+}
+
+#[test]
+fn send_queue_is_cut_at_the_peers_mss() {
+    let (mut iface, peer) = Peer::new(|b| b);
+    let mut listener = iface.bind(80).unwrap();
+    let (seq, rcv_nxt, _) = peer.handshake(80, 1000, &[TcpOptionElement::MaximumSegmentSize(500)]);
+    let mut stream = listener.accept_timeout(Duration::from_secs(1)).unwrap();
+    stream.write_all(&[1; 3000]).unwrap();
+
+    // The initial window of 2000 bytes goes out at once, in full segments
+    let mut received = 0;
+    while received < 2000 {
+        let (_, payload) = peer
+            .recv(Duration::from_millis(200))
+            .expect("window not filled");
+        assert_eq!(payload.len(), 500);
+        received += payload.len();
+    }
+
+    // An ACK clocks out the rest
+    let mut ack = peer.header(80, seq, 65535);
+    ack.ack = true;
+    ack.acknowledgment_number = rcv_nxt.wrapping_add(received as u32);
+    peer.send(ack, &[]);
+    while received < 3000 {
+        let (_, payload) = peer
+            .recv(Duration::from_millis(200))
+            .expect("window not filled");
+        assert_eq!(payload.len(), 500);
+        received += payload.len();
+    }
+}
@@ -0,0 +1,89 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::SocketAddr,
+    thread,
+    time::Duration,
+};
+
+mod common;
+
+use common::{Peer, link};
+
+#[test]
+fn huge_timeouts_mean_no_deadline() {
+    let (mut server, mut client) = link();
+    let mut listener = server.bind(80).unwrap();
+
+    let peer = thread::spawn(move || {
+        let mut stream = client
+            .connect(SocketAddr::from(([10, 0, 0, 1], 80)))
+            .unwrap();
+        thread::sleep(Duration::from_millis(50));
+        stream.write_all(b"ping").unwrap();
+        stream.shutdown().unwrap();
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).unwrap();
+        client
+    });
+
+    let mut stream = listener.accept_timeout(Duration::MAX).unwrap();
+    stream.set_read_timeout(Some(Duration::MAX)).unwrap();
+    stream.set_write_timeout(Some(Duration::MAX)).unwrap();
+    let mut buf = [0; 4];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"ping");
+    stream.write_all(b"pong").unwrap();
+    stream.flush().unwrap();
+    drop(stream);
+    let _client = peer.join().unwrap();
+
+    // The interface survived
+    assert!(server.bind(81).is_ok());
+}
+
+#[test]
+fn short_read_timeout_expires() {
+    let (mut server, mut client) = link();
+    let _listener = server.bind(80).unwrap();
+    let mut stream = client
+        .connect(SocketAddr::from(([10, 0, 0, 1], 80)))
+        .unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_millis(50)))
+        .unwrap();
+    let err = stream.read(&mut [0; 16]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+}
+
+#[test]
+fn udp_huge_timeouts_mean_no_deadline() {
+    let (mut server, mut client) = link();
+    let a = server.bind_udp(53).unwrap();
+    let b = client.bind_udp(0).unwrap();
+    a.set_read_timeout(Some(Duration::MAX)).unwrap();
+    b.set_write_timeout(Some(Duration::MAX)).unwrap();
+    b.send_to(b"query", SocketAddr::from(([10, 0, 0, 1], 53)))
+        .unwrap();
+    let mut buf = [0; 16];
+    let (n, _) = a.recv_from(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"query");
+}
+
+#[test]
+fn reset_fails_a_pending_read() {
+    let (mut iface, peer) = Peer::new(|b| b);
+    let mut listener = iface.bind(80).unwrap();
+    let (seq, ack) = peer.connect(80, 1000);
+    let mut stream = listener.accept_timeout(Duration::from_secs(1)).unwrap();
+
+    let mut rst = peer.header(80, seq, 0);
+    rst.rst = true;
+    rst.ack = true;
+    rst.acknowledgment_number = ack;
+    peer.send(rst, &[]);
+
+    let err = stream.read(&mut [0; 16]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionReset);
+    let err = stream.write(b"x").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionReset);
+}
@@ -0,0 +1,69 @@
+use std::{io::Read, time::Duration};
+
+mod common;
+
+use common::Peer;
+use etherparse::{TcpHeader, TcpOptionElement};
+
+const WAIT: Duration = Duration::from_secs(1);
+
+fn timestamp(tcp: &TcpHeader) -> Option<(u32, u32)> {
+    tcp.options_iterator().find_map(|o| match o.unwrap() {
+        TcpOptionElement::Timestamp(tsval, tsecr) => Some((tsval, tsecr)),
+        _ => None,
+    })
+}
+
+#[test]
+fn timestamps_are_echoed_and_old_segments_rejected() {
+    let (mut iface, peer) = Peer::new(|b| b);
+    let mut listener = iface.bind(80).unwrap();
+
+    let mut syn = peer.header(80, 1000, 65535);
+    syn.syn = true;
+    syn.set_options(&[TcpOptionElement::Timestamp(100, 0)])
+        .unwrap();
+    peer.send(syn, &[]);
+    let (syn_ack, _) = peer.recv(WAIT).unwrap();
+    let (_, tsecr) = timestamp(&syn_ack).expect("no timestamps in the SYN-ACK");
+    assert_eq!(tsecr, 100);
+
+    let segment = |seq: u32, tsval: u32| {
+        let mut tcp = peer.header(80, seq, 65535);
+        tcp.ack = true;
+        tcp.acknowledgment_number = syn_ack.sequence_number.wrapping_add(1);
+        tcp.set_options(&[TcpOptionElement::Timestamp(tsval, 0)])
+            .unwrap();
+        tcp
+    };
+    peer.send(segment(1001, 150), &[]);
+    let mut stream = listener.accept_timeout(WAIT).unwrap();
+
+    peer.send(segment(1001, 200), b"a");
+    let (ack, _) = peer.recv(WAIT).unwrap();
+    assert_eq!(ack.acknowledgment_number, 1002);
+    assert_eq!(timestamp(&ack).unwrap().1, 200);
+
+    // PAWS: an older TSval marks an old duplicate, it is only acknowledged
+    peer.send(segment(1002, 150), b"x");
+    let (ack, _) = peer.recv(WAIT).unwrap();
+    assert_eq!(ack.acknowledgment_number, 1002);
+    assert_eq!(timestamp(&ack).unwrap().1, 200);
+
+    peer.send(segment(1002, 300), b"b");
+    let (ack, _) = peer.recv(WAIT).unwrap();
+    assert_eq!(ack.acknowledgment_number, 1003);
+    assert_eq!(timestamp(&ack).unwrap().1, 300);
+
+    let mut buf = [0; 2];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"ab");
+}
+
+#[test]
+fn no_timestamps_without_the_peers_option() {
+    let (mut iface, peer) = Peer::new(|b| b);
+    let _listener = iface.bind(80).unwrap();
+    let (_, _, syn_ack) = peer.handshake(80, 1000, &[]);
+    assert_eq!(timestamp(&syn_ack), None);
+}
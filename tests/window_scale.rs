@@ -0,0 +1,65 @@
+use std::{io::Write, thread, time::Duration};
+
+mod common;
+
+use common::Peer;
+use etherparse::{TcpHeader, TcpOptionElement};
+
+const WAIT: Duration = Duration::from_secs(1);
+
+fn window_scale(tcp: &TcpHeader) -> Option<u8> {
+    tcp.options_iterator().find_map(|o| match o.unwrap() {
+        TcpOptionElement::WindowScale(shift) => Some(shift),
+        _ => None,
+    })
+}
+
+#[test]
+fn receive_window_is_scaled_past_64_kib() {
+    let (mut iface, peer) = Peer::new(|b| b.recv_buffer_size(1 << 20));
+    let _listener = iface.bind(80).unwrap();
+    let (seq, _, syn_ack) = peer.handshake(80, 1000, &[TcpOptionElement::WindowScale(7)]);
+    let shift = window_scale(&syn_ack).expect("no window scale option");
+    assert!(shift > 0);
+    // The window of a SYN is never scaled
+    assert_eq!(syn_ack.window_size, u16::MAX);
+
+    let mut data = peer.header(80, seq, 65535);
+    data.ack = true;
+    data.acknowledgment_number = syn_ack.sequence_number.wrapping_add(1);
+    peer.send(data, &[0; 100]);
+    let (ack, _) = peer.recv(WAIT).unwrap();
+    let window = (ack.window_size as u32) << shift;
+    assert!(window > u16::MAX as u32);
+    assert!((1 << 20) - 100 - window < 1 << shift);
+}
+
+#[test]
+fn no_scaling_without_the_peers_option() {
+    let (mut iface, peer) = Peer::new(|b| b.recv_buffer_size(1 << 20));
+    let _listener = iface.bind(80).unwrap();
+    let (_, _, syn_ack) = peer.handshake(80, 1000, &[]);
+    assert_eq!(window_scale(&syn_ack), None);
+}
+
+#[test]
+fn send_window_is_scaled_by_the_peers_shift() {
+    let (mut iface, peer) = Peer::new(|b| b);
+    let mut listener = iface.bind(80).unwrap();
+    let (seq, rcv_nxt, _) = peer.handshake(80, 1000, &[TcpOptionElement::WindowScale(7)]);
+    let mut stream = listener.accept_timeout(WAIT).unwrap();
+
+    // 2 << 7 bytes
+    let mut ack = peer.header(80, seq, 2);
+    ack.ack = true;
+    ack.acknowledgment_number = rcv_nxt;
+    peer.send(ack, &[]);
+    thread::sleep(Duration::from_millis(50));
+    stream.write_all(&[1; 1000]).unwrap();
+
+    let mut received = 0;
+    while let Some((_, payload)) = peer.recv(Duration::from_millis(200)) {
+        received += payload.len();
+    }
+    assert_eq!(received, 256);
+}